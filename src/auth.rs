@@ -0,0 +1,199 @@
+use bevy::log::{debug, info};
+use bevy::prelude::Resource;
+use rand::RngCore;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+use sha1::{Digest, Sha1};
+use valence_protocol::uuid::Uuid;
+use crate::error::NetworkError;
+
+/// Mojang's session server. Override with [`SESSION_SERVER_ENV`] to test against a mock.
+const DEFAULT_SESSION_SERVER: &str = "https://sessionserver.mojang.com";
+pub(crate) const SESSION_SERVER_ENV: &str = "BEVY_CLIENT_SESSION_SERVER";
+pub(crate) const ACCESS_TOKEN_ENV: &str = "BEVY_CLIENT_ACCESS_TOKEN";
+pub(crate) const PROFILE_ID_ENV: &str = "BEVY_CLIENT_PROFILE_ID";
+
+/// Credentials for online-mode servers. Without an access token only offline servers work.
+#[derive(Resource, Clone, Debug)]
+pub(crate) struct AuthConfig {
+    /// Base URL; `/session/minecraft/join` is appended.
+    pub(crate) session_server: String,
+    pub(crate) access_token: Option<String>,
+    pub(crate) profile_id: Option<Uuid>,
+}
+
+impl AuthConfig {
+    pub(crate) fn from_env() -> Self {
+        Self {
+            session_server: std::env::var(SESSION_SERVER_ENV)
+                .unwrap_or_else(|_| DEFAULT_SESSION_SERVER.to_string()),
+            access_token: std::env::var(ACCESS_TOKEN_ENV).ok(),
+            profile_id: std::env::var(PROFILE_ID_ENV).ok().and_then(|id| Uuid::parse_str(&id).ok()),
+        }
+    }
+}
+
+/// What goes back to the server in `LoginKeyC2s`, plus the key for the stream cipher.
+pub(crate) struct KeyExchange {
+    pub(crate) shared_secret: [u8; 16],
+    pub(crate) encrypted_secret: Vec<u8>,
+    pub(crate) encrypted_verify_token: Vec<u8>,
+}
+
+/// Handles the encryption request: picks a shared secret, registers the join with the
+/// session server and RSA-encrypts the secret and verify token with the server's key.
+pub(crate) fn exchange_keys(
+    config: &AuthConfig,
+    server_id: &str,
+    public_key_der: &[u8],
+    verify_token: &[u8],
+) -> Result<KeyExchange, NetworkError> {
+    let mut shared_secret = [0; 16];
+    rand::thread_rng().fill_bytes(&mut shared_secret);
+
+    let public_key = RsaPublicKey::from_public_key_der(public_key_der)
+        .map_err(|e| NetworkError::Auth(format!("bad server public key: {}", e)))?;
+
+    join_session(config, &server_hash(server_id, &shared_secret, public_key_der))?;
+
+    let mut rng = rand::thread_rng();
+    let encrypted_secret = public_key
+        .encrypt(&mut rng, Pkcs1v15Encrypt, &shared_secret)
+        .map_err(|e| NetworkError::Auth(format!("failed to encrypt shared secret: {}", e)))?;
+    let encrypted_verify_token = public_key
+        .encrypt(&mut rng, Pkcs1v15Encrypt, verify_token)
+        .map_err(|e| NetworkError::Auth(format!("failed to encrypt verify token: {}", e)))?;
+
+    Ok(KeyExchange {
+        shared_secret,
+        encrypted_secret,
+        encrypted_verify_token,
+    })
+}
+
+/// Tells the session server we're joining the server identified by `server_hash`.
+fn join_session(config: &AuthConfig, server_hash: &str) -> Result<(), NetworkError> {
+    let (Some(access_token), Some(profile_id)) = (&config.access_token, config.profile_id) else {
+        return Err(NetworkError::Auth(format!(
+            "server is in online mode, set {} and {}",
+            ACCESS_TOKEN_ENV, PROFILE_ID_ENV
+        )));
+    };
+
+    let url = format!("{}/session/minecraft/join", config.session_server.trim_end_matches('/'));
+    let body = serde_json::json!({
+        "accessToken": access_token,
+        "selectedProfile": profile_id.simple().to_string(),
+        "serverId": server_hash,
+    });
+    debug!("Joining session at {} with server hash {}", url, server_hash);
+
+    match ureq::post(&url).send_json(body) {
+        Ok(response) => {
+            info!("Session join accepted ({})", response.status());
+            Ok(())
+        }
+        Err(ureq::Error::Status(code, response)) => {
+            let text = response.into_string().unwrap_or_default();
+            Err(NetworkError::Auth(format!("session server refused join ({}): {}", code, text)))
+        }
+        Err(e) => Err(NetworkError::Auth(format!("session server unreachable: {}", e))),
+    }
+}
+
+/// Minecraft's server hash: SHA-1 over server id, shared secret and public key, printed as
+/// a signed (two's complement) hex number without leading zeros.
+fn server_hash(server_id: &str, shared_secret: &[u8], public_key_der: &[u8]) -> String {
+    let mut digest: [u8; 20] = Sha1::new()
+        .chain_update(server_id.as_bytes())
+        .chain_update(shared_secret)
+        .chain_update(public_key_der)
+        .finalize()
+        .into();
+
+    let negative = digest[0] & 0x80 != 0;
+    if negative {
+        // Two's complement: invert and add one.
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            let (value, overflow) = (!*byte).overflowing_add(carry as u8);
+            *byte = value;
+            carry = overflow;
+        }
+    }
+
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let hex = hex.trim_start_matches('0');
+    if negative {
+        format!("-{}", hex)
+    } else {
+        hex.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn server_hash_matches_known_vectors() {
+        assert_eq!(server_hash("Notch", &[], &[]), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+        assert_eq!(server_hash("jeb_", &[], &[]), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+        assert_eq!(server_hash("simon", &[], &[]), "88e16a1019277b15d58faf0541e11910eb756f6");
+    }
+
+    /// Answers one HTTP request with 204 and hands back its request line and body.
+    fn accept_one_request(listener: TcpListener) -> thread::JoinHandle<(String, String)> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            reader.get_mut().write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n").unwrap();
+            (request_line.trim().to_string(), String::from_utf8(body).unwrap())
+        })
+    }
+
+    #[test]
+    fn join_session_posts_to_configured_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = accept_one_request(listener.try_clone().unwrap());
+        let profile_id = Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        let config = AuthConfig {
+            session_server: format!("http://{}/", listener.local_addr().unwrap()),
+            access_token: Some("token".to_string()),
+            profile_id: Some(profile_id),
+        };
+        join_session(&config, "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1").unwrap();
+
+        let (request_line, body) = server.join().unwrap();
+        assert_eq!(request_line, "POST /session/minecraft/join HTTP/1.1");
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "accessToken": "token",
+                "selectedProfile": "069a79f444e94726a5befca90e38aaf5",
+                "serverId": "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1",
+            })
+        );
+    }
+}
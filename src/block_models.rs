@@ -0,0 +1,227 @@
+use std::sync::OnceLock;
+use valence_protocol::block::{BlockKind, PropName, PropValue};
+use valence_protocol::BlockState;
+
+/// An axis-aligned box inside a block, in block units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ModelBox {
+    pub(crate) from: [f32; 3],
+    pub(crate) to: [f32; 3],
+}
+
+impl ModelBox {
+    pub(crate) const FULL: ModelBox = ModelBox { from: [0.0; 3], to: [1.0; 3] };
+
+    /// Maps a unit cube corner onto this box.
+    pub(crate) fn corner(&self, corner: [f32; 3]) -> [f32; 3] {
+        std::array::from_fn(|axis| self.from[axis] + (self.to[axis] - self.from[axis]) * corner[axis])
+    }
+
+    /// Whether the box's face in mesher order lies on the block's side.
+    pub(crate) fn touches_side(&self, face: usize) -> bool {
+        let axis = face / 2;
+        if face % 2 == 0 {
+            self.to[axis] >= 1.0
+        } else {
+            self.from[axis] <= 0.0
+        }
+    }
+
+    /// Whether the box's face fills the whole side of the block.
+    fn covers_side(&self, face: usize) -> bool {
+        self.touches_side(face)
+            && (0..3).filter(|&axis| axis != face / 2).all(|axis| self.from[axis] <= 0.0 && self.to[axis] >= 1.0)
+    }
+
+    /// Turned clockwise seen from above, so a box on the north side ends up on the east.
+    fn rotated(self, quarter_turns: u8) -> Self {
+        let mut rotated = self;
+        for _ in 0..quarter_turns % 4 {
+            let ModelBox { from, to } = rotated;
+            rotated = ModelBox { from: [1.0 - to[2], from[1], from[0]], to: [1.0 - from[2], to[1], to[0]] };
+        }
+        rotated
+    }
+
+    /// Upside down, for top halves and ceilings.
+    fn flipped(self) -> Self {
+        let ModelBox { from, to } = self;
+        ModelBox { from: [from[0], 1.0 - to[1], from[2]], to: [to[0], 1.0 - from[1], to[2]] }
+    }
+}
+
+/// A box given in sixteenths, like resource pack models.
+fn px(from: [f32; 3], to: [f32; 3]) -> ModelBox {
+    ModelBox { from: from.map(|v| v / 16.0), to: to.map(|v| v / 16.0) }
+}
+
+/// Families of blocks that share a shape, told apart by their properties.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Shape {
+    Cube,
+    Slab,
+    Stairs,
+    Fence,
+    Wall,
+    Pane,
+    Door,
+    Trapdoor,
+    Torch,
+    WallTorch,
+    Dust,
+    Lever,
+}
+
+fn shape(kind: BlockKind) -> Shape {
+    // Looked up for every face and corner the mesher considers, so classify by name once.
+    static SHAPES: OnceLock<Vec<Shape>> = OnceLock::new();
+    SHAPES.get_or_init(|| BlockKind::ALL.iter().map(|&kind| classify(kind)).collect())[kind as usize]
+}
+
+fn classify(kind: BlockKind) -> Shape {
+    let name = kind.to_str();
+    match kind {
+        BlockKind::Torch | BlockKind::SoulTorch | BlockKind::RedstoneTorch => Shape::Torch,
+        BlockKind::WallTorch | BlockKind::SoulWallTorch | BlockKind::RedstoneWallTorch => Shape::WallTorch,
+        BlockKind::RedstoneWire => Shape::Dust,
+        BlockKind::Lever => Shape::Lever,
+        BlockKind::IronBars => Shape::Pane,
+        _ if name.ends_with("_slab") => Shape::Slab,
+        _ if name.ends_with("_stairs") => Shape::Stairs,
+        _ if name.ends_with("_fence") => Shape::Fence,
+        _ if name.ends_with("_wall") => Shape::Wall,
+        _ if name.ends_with("_pane") => Shape::Pane,
+        _ if name.ends_with("_trapdoor") => Shape::Trapdoor,
+        _ if name.ends_with("_door") => Shape::Door,
+        _ => Shape::Cube,
+    }
+}
+
+pub(crate) fn is_full_cube(state: BlockState) -> bool {
+    match shape(state.to_kind()) {
+        Shape::Cube => true,
+        Shape::Slab => state.get(PropName::Type) == Some(PropValue::Double),
+        _ => false,
+    }
+}
+
+/// Opaque full cubes, the only blocks that darken corners and block smooth lighting.
+pub(crate) fn is_solid_cube(state: BlockState) -> bool {
+    state.is_opaque() && is_full_cube(state)
+}
+
+/// Whether `state` hides a neighbour's face on its side `face`, in mesher order.
+/// Slabs and stairs only do on the sides they fill completely.
+pub(crate) fn covers_face(state: BlockState, face: usize) -> bool {
+    if !state.is_opaque() {
+        return false;
+    }
+    is_full_cube(state) || block_boxes(state).iter().any(|model_box| model_box.covers_side(face))
+}
+
+/// Sides with connection properties, as quarter turns from north.
+const SIDES: [(PropName, u8); 4] = [(PropName::North, 0), (PropName::East, 1), (PropName::South, 2), (PropName::West, 3)];
+
+/// The boxes a block state is drawn with; a single full box for plain cubes.
+pub(crate) fn block_boxes(state: BlockState) -> Vec<ModelBox> {
+    let is = |name: PropName, value: PropValue| state.get(name) == Some(value);
+    // Shapes below are built facing north and turned into place.
+    let turns = match state.get(PropName::Facing) {
+        Some(PropValue::East) => 1,
+        Some(PropValue::South) => 2,
+        Some(PropValue::West) => 3,
+        _ => 0,
+    };
+    let connected = |value: PropValue| SIDES.into_iter().filter(move |&(name, _)| is(name, value)).map(|(_, turns)| turns);
+
+    match shape(state.to_kind()) {
+        Shape::Cube => vec![ModelBox::FULL],
+        Shape::Slab => match state.get(PropName::Type) {
+            Some(PropValue::Top) => vec![px([0.0, 8.0, 0.0], [16.0, 16.0, 16.0])],
+            Some(PropValue::Double) => vec![ModelBox::FULL],
+            _ => vec![px([0.0, 0.0, 0.0], [16.0, 8.0, 16.0])],
+        },
+        Shape::Stairs => {
+            // Steps as [x0, z0, x1, z1] on top of the half slab; left is west when facing north.
+            let steps: &[[f32; 4]] = match state.get(PropName::Shape) {
+                Some(PropValue::OuterLeft) => &[[0.0, 0.0, 8.0, 8.0]],
+                Some(PropValue::OuterRight) => &[[8.0, 0.0, 16.0, 8.0]],
+                Some(PropValue::InnerLeft) => &[[0.0, 0.0, 16.0, 8.0], [0.0, 8.0, 8.0, 16.0]],
+                Some(PropValue::InnerRight) => &[[0.0, 0.0, 16.0, 8.0], [8.0, 8.0, 16.0, 16.0]],
+                _ => &[[0.0, 0.0, 16.0, 8.0]],
+            };
+            let upside_down = is(PropName::Half, PropValue::Top);
+            std::iter::once(px([0.0, 0.0, 0.0], [16.0, 8.0, 16.0]))
+                .chain(steps.iter().map(|&[x0, z0, x1, z1]| px([x0, 8.0, z0], [x1, 16.0, z1])))
+                .map(|model_box| if upside_down { model_box.flipped() } else { model_box })
+                .map(|model_box| model_box.rotated(turns))
+                .collect()
+        }
+        Shape::Fence => {
+            let mut boxes = vec![px([6.0, 0.0, 6.0], [10.0, 16.0, 10.0])];
+            for side in connected(PropValue::True) {
+                boxes.push(px([7.0, 12.0, 0.0], [9.0, 15.0, 6.0]).rotated(side));
+                boxes.push(px([7.0, 6.0, 0.0], [9.0, 9.0, 6.0]).rotated(side));
+            }
+            boxes
+        }
+        Shape::Wall => {
+            let mut boxes = Vec::new();
+            if is(PropName::Up, PropValue::True) {
+                boxes.push(px([4.0, 0.0, 4.0], [12.0, 16.0, 12.0]));
+            }
+            boxes.extend(connected(PropValue::Low).map(|side| px([5.0, 0.0, 0.0], [11.0, 14.0, 8.0]).rotated(side)));
+            boxes.extend(connected(PropValue::Tall).map(|side| px([5.0, 0.0, 0.0], [11.0, 16.0, 8.0]).rotated(side)));
+            boxes
+        }
+        Shape::Pane => {
+            let mut boxes = vec![px([7.0, 0.0, 7.0], [9.0, 16.0, 9.0])];
+            boxes.extend(connected(PropValue::True).map(|side| px([7.0, 0.0, 0.0], [9.0, 16.0, 7.0]).rotated(side)));
+            boxes
+        }
+        Shape::Door => {
+            // A closed door sits on the side away from its facing; an open one swings
+            // a quarter turn around its hinge.
+            let swing = match (is(PropName::Open, PropValue::True), is(PropName::Hinge, PropValue::Right)) {
+                (false, _) => 0,
+                (true, true) => 3,
+                (true, false) => 1,
+            };
+            vec![px([0.0, 0.0, 13.0], [16.0, 16.0, 16.0]).rotated(turns + swing)]
+        }
+        Shape::Trapdoor => {
+            if is(PropName::Open, PropValue::True) {
+                vec![px([0.0, 0.0, 13.0], [16.0, 16.0, 16.0]).rotated(turns)]
+            } else if is(PropName::Half, PropValue::Top) {
+                vec![px([0.0, 13.0, 0.0], [16.0, 16.0, 16.0])]
+            } else {
+                vec![px([0.0, 0.0, 0.0], [16.0, 3.0, 16.0])]
+            }
+        }
+        Shape::Torch => vec![px([7.0, 0.0, 7.0], [9.0, 10.0, 9.0])],
+        // Upright against the wall behind it rather than leaning out like vanilla's.
+        Shape::WallTorch => vec![px([7.0, 3.0, 12.0], [9.0, 13.0, 14.0]).rotated(turns)],
+        Shape::Dust => {
+            const THICKNESS: f32 = 0.25;
+            let mut boxes = vec![px([5.0, 0.0, 5.0], [11.0, THICKNESS, 11.0])];
+            let sides: Vec<u8> = connected(PropValue::Side).chain(connected(PropValue::Up)).collect();
+            // Unconnected dust is drawn as a cross, as in vanilla.
+            let arms = if sides.is_empty() { vec![0, 1, 2, 3] } else { sides };
+            boxes.extend(arms.into_iter().map(|side| px([5.0, 0.0, 0.0], [11.0, THICKNESS, 5.0]).rotated(side)));
+            boxes.extend(connected(PropValue::Up).map(|side| px([5.0, 0.0, 0.0], [11.0, 16.0, THICKNESS]).rotated(side)));
+            boxes
+        }
+        Shape::Lever => {
+            let (base, handle) = match state.get(PropName::Face) {
+                Some(PropValue::Wall) => (px([5.0, 4.0, 13.0], [11.0, 12.0, 16.0]), px([7.0, 7.0, 5.0], [9.0, 9.0, 13.0])),
+                _ => (px([5.0, 0.0, 4.0], [11.0, 3.0, 12.0]), px([7.0, 3.0, 7.0], [9.0, 11.0, 9.0])),
+            };
+            let on_ceiling = is(PropName::Face, PropValue::Ceiling);
+            [base, handle]
+                .into_iter()
+                .map(|model_box| if on_ceiling { model_box.flipped() } else { model_box })
+                .map(|model_box| model_box.rotated(turns))
+                .collect()
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use bevy::asset::Assets;
+use bevy::color::{Alpha, Color};
+use bevy::log::{info, warn};
+use bevy::prelude::{Commands, Handle, Image, ResMut, Resource};
+use valence_protocol::block::{BlockKind, PropName, PropValue};
+use valence_protocol::BlockState;
+use crate::block_models::ModelBox;
+use crate::mesher::rgb;
+use crate::resource_pack::{load_resource_pack, ResourcePack};
+
+/// Folder or zip of a vanilla-format resource pack to take block textures from.
+pub(crate) const RESOURCE_PACK_ENV: &str = "BEVY_CLIENT_RESOURCE_PACK";
+
+/// Face order used throughout the mesher: +X, -X, +Y, -Y, +Z, -Z.
+pub(crate) const FACE_TOP: usize = 2;
+
+/// Blocks at least this bright are drawn at full brightness, like torches and lamps.
+/// Dimmer ones, such as magma or brown mushrooms, are lit normally.
+const EMISSIVE_LUMINANCE: u8 = 10;
+
+/// How a block lets light and neighbouring faces through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Transparency {
+    Opaque,
+    /// Fully transparent holes, like leaves, flowers or plain glass.
+    Cutout,
+    /// Partially see-through, like water, ice or stained glass.
+    Translucent,
+}
+
+/// Biome-dependent or fixed color multiplied into a block's texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Tint {
+    Grass,
+    Foliage,
+    Water,
+    Fixed(u32),
+}
+
+/// Liquids, which are meshed by level instead of as cubes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Fluid {
+    Water,
+    Lava,
+}
+
+impl Fluid {
+    /// Texture name prefix, as in `water_still` and `water_flow`.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Fluid::Water => "water",
+            Fluid::Lava => "lava",
+        }
+    }
+
+    /// The plain fluid block, whose appearance the fluid is drawn with.
+    pub(crate) fn block(self) -> BlockState {
+        match self {
+            Fluid::Water => BlockKind::Water.to_state(),
+            Fluid::Lava => BlockKind::Lava.to_state(),
+        }
+    }
+}
+
+/// The fluid in a block and its level: 0 for a source, 1 to 7 as it spreads out, 8 and
+/// up when falling. Waterlogged blocks hold a water source.
+pub(crate) fn fluid_state(state: BlockState) -> Option<(Fluid, u8)> {
+    let fluid = match state.to_kind() {
+        BlockKind::Water | BlockKind::BubbleColumn => Fluid::Water,
+        BlockKind::Lava => Fluid::Lava,
+        _ if state.get(PropName::Waterlogged) == Some(PropValue::True) => return Some((Fluid::Water, 0)),
+        _ => return None,
+    };
+    let level = state.get(PropName::Level).and_then(PropValue::to_u16).unwrap_or(0);
+    Some((fluid, level as u8))
+}
+
+/// Texture rectangle in the atlas as `[u0, v0, u1, v1]`.
+pub(crate) type AtlasRect = [f32; 4];
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FaceAppearance {
+    pub(crate) texture: Option<AtlasRect>,
+    /// Whether the block's tint applies to this face when textured.
+    pub(crate) tinted: bool,
+    /// Quarter turns the texture is rotated clockwise on the face.
+    pub(crate) rotation: u8,
+}
+
+/// A box of a resource pack model, turned into place, with the faces it draws.
+#[derive(Clone, Debug)]
+pub(crate) struct BlockElement {
+    pub(crate) model_box: ModelBox,
+    /// Indexed like the mesher's faces; `None` where the model leaves a side open.
+    pub(crate) faces: [Option<FaceAppearance>; 6],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BlockAppearance {
+    /// Flat color used when the face has no texture.
+    pub(crate) base_color: Color,
+    pub(crate) tint: Option<Tint>,
+    /// Indexed like the mesher's faces; used for the boxes of blocks without a model.
+    pub(crate) faces: [FaceAppearance; 6],
+    pub(crate) transparency: Transparency,
+    /// Glows regardless of lighting, e.g. a lit redstone lamp.
+    pub(crate) emissive: bool,
+}
+
+/// Maps block states to what they look like. Textures are optional; without a resource
+/// pack every block is drawn in a flat base color.
+#[derive(Resource, Default)]
+pub(crate) struct BlockAppearances {
+    /// Textured elements of each state the resource pack has a model for.
+    elements: HashMap<BlockState, Vec<BlockElement>>,
+    /// Average texture color, preferred over the built-in table when available.
+    texture_colors: HashMap<BlockKind, Color>,
+    pub(crate) atlas: Option<Handle<Image>>,
+    /// Where a plain white texel sits in the atlas, for untextured faces.
+    pub(crate) white: AtlasRect,
+    /// Indexed by [`Fluid`].
+    pub(crate) fluid_still: [Option<AtlasRect>; 2],
+    pub(crate) fluid_flow: [Option<Handle<Image>>; 2],
+    /// Crack textures in the atlas, one per break progress stage.
+    pub(crate) destroy_stages: [Option<AtlasRect>; 10],
+}
+
+impl BlockAppearances {
+    pub(crate) fn appearance(&self, state: BlockState) -> BlockAppearance {
+        let kind = state.to_kind();
+        let tint = block_tint(kind);
+        let faces = std::array::from_fn(|face| FaceAppearance {
+            texture: None,
+            tinted: tint.is_some() && (kind != BlockKind::GrassBlock || face == FACE_TOP),
+            rotation: 0,
+        });
+
+        let transparency = block_transparency(state);
+        let mut base_color = self.texture_colors.get(&kind).copied().unwrap_or_else(|| base_color(state));
+        let textured = self.elements(state).is_some_and(|elements| {
+            elements.iter().flat_map(|element| element.faces.iter().flatten()).any(|face| face.texture.is_some())
+        });
+        if transparency == Transparency::Translucent && !textured {
+            base_color.set_alpha(0.6);
+        }
+
+        BlockAppearance {
+            base_color,
+            tint,
+            faces,
+            transparency,
+            emissive: state.luminance() >= EMISSIVE_LUMINANCE,
+        }
+    }
+
+    /// The resource pack model of `state`, if there is one.
+    pub(crate) fn elements(&self, state: BlockState) -> Option<&[BlockElement]> {
+        self.elements.get(&state).map(Vec::as_slice)
+    }
+
+    /// Switches to the textures of `pack`, which already holds whatever it was layered
+    /// over. Existing meshes keep the old UVs until rebuilt.
+    pub(crate) fn apply_pack(&mut self, pack: ResourcePack, images: &mut Assets<Image>) {
+        self.elements = pack.elements;
+        self.texture_colors = pack.colors;
+        self.white = pack.white;
+        self.atlas = Some(images.add(pack.atlas));
+        self.fluid_still = pack.fluid_still;
+        self.fluid_flow = pack.fluid_flow.map(|image| image.map(|image| images.add(image)));
+        self.destroy_stages = pack.destroy_stages;
+    }
+}
+
+/// The pack set with [`RESOURCE_PACK_ENV`], which server packs are layered on top of.
+pub(crate) fn base_pack_path() -> Option<PathBuf> {
+    std::env::var_os(RESOURCE_PACK_ENV).map(PathBuf::from)
+}
+
+pub(crate) fn load_block_appearances(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let mut appearances = BlockAppearances::default();
+
+    if let Some(path) = base_pack_path() {
+        match load_resource_pack(std::slice::from_ref(&path)) {
+            Some(pack) => {
+                info!("Loaded block textures for {} states from {}", pack.elements.len(), path.display());
+                appearances.apply_pack(pack, &mut images);
+            }
+            None => warn!("No block textures found in {}, using flat colors", path.display()),
+        }
+    }
+
+    commands.insert_resource(appearances);
+}
+
+fn block_transparency(state: BlockState) -> Transparency {
+    if state.is_opaque() {
+        return Transparency::Opaque;
+    }
+    let kind = state.to_kind();
+    match kind {
+        BlockKind::Water | BlockKind::BubbleColumn | BlockKind::Ice | BlockKind::FrostedIce
+        | BlockKind::SlimeBlock | BlockKind::HoneyBlock | BlockKind::TintedGlass => Transparency::Translucent,
+        _ if kind.to_str().contains("stained_glass") => Transparency::Translucent,
+        _ => Transparency::Cutout,
+    }
+}
+
+fn block_tint(kind: BlockKind) -> Option<Tint> {
+    match kind {
+        BlockKind::GrassBlock | BlockKind::Grass | BlockKind::TallGrass | BlockKind::Fern
+        | BlockKind::LargeFern | BlockKind::SugarCane => Some(Tint::Grass),
+        BlockKind::OakLeaves | BlockKind::JungleLeaves | BlockKind::AcaciaLeaves | BlockKind::DarkOakLeaves
+        | BlockKind::MangroveLeaves | BlockKind::Vine => Some(Tint::Foliage),
+        BlockKind::SpruceLeaves => Some(Tint::Fixed(0x619961)),
+        BlockKind::BirchLeaves => Some(Tint::Fixed(0x80a755)),
+        BlockKind::LilyPad => Some(Tint::Fixed(0x208030)),
+        BlockKind::Water | BlockKind::BubbleColumn | BlockKind::WaterCauldron => Some(Tint::Water),
+        // Vanilla shades dust by its power; this sits between unpowered and fully powered.
+        BlockKind::RedstoneWire => Some(Tint::Fixed(0x960000)),
+        _ => None,
+    }
+}
+
+/// Approximate colors of common blocks for drawing without textures. Anything else gets a
+/// stable color derived from its kind.
+fn base_color(state: BlockState) -> Color {
+    let kind = state.to_kind();
+    let hex = match kind {
+        BlockKind::Stone | BlockKind::Andesite | BlockKind::StoneBricks => 0x7d7d7d,
+        BlockKind::Cobblestone => 0x7a7a7a,
+        BlockKind::Deepslate | BlockKind::CobbledDeepslate => 0x505055,
+        BlockKind::Granite => 0x956756,
+        BlockKind::Diorite => 0xbcbcbc,
+        BlockKind::Dirt | BlockKind::DirtPath | BlockKind::Farmland => 0x866043,
+        BlockKind::CoarseDirt | BlockKind::RootedDirt => 0x77553b,
+        BlockKind::Podzol => 0x5b3f18,
+        BlockKind::Mud => 0x3c393d,
+        BlockKind::Sand => 0xdbcfa3,
+        BlockKind::RedSand => 0xbe6621,
+        BlockKind::Gravel => 0x837f7e,
+        BlockKind::Sandstone => 0xd8cb9b,
+        BlockKind::Clay => 0xa0a6b3,
+        BlockKind::Bedrock => 0x555555,
+        BlockKind::OakLog | BlockKind::OakWood => 0x6d5533,
+        BlockKind::SpruceLog | BlockKind::SpruceWood => 0x3a2611,
+        BlockKind::BirchLog | BlockKind::BirchWood => 0xd5d3ca,
+        BlockKind::OakPlanks => 0xa2834f,
+        BlockKind::SprucePlanks => 0x73552f,
+        BlockKind::BirchPlanks => 0xc0af79,
+        BlockKind::Glass | BlockKind::GlassPane => 0xc0dfe6,
+        BlockKind::Snow | BlockKind::SnowBlock | BlockKind::PowderSnow => 0xf9fefe,
+        BlockKind::Ice | BlockKind::FrostedIce => 0x91b7fd,
+        BlockKind::PackedIce | BlockKind::BlueIce => 0x8db4fa,
+        BlockKind::Lava => 0xcf5b14,
+        BlockKind::CoalOre => 0x737373,
+        BlockKind::IronOre => 0x887f76,
+        BlockKind::Netherrack => 0x6f3634,
+        BlockKind::Obsidian => 0x0f0b19,
+        BlockKind::Glowstone => 0xab8654,
+        BlockKind::RedstoneLamp => match state.get(PropName::Lit) {
+            Some(PropValue::True) => 0xdfa86a,
+            _ => 0x5f3a1d,
+        },
+        BlockKind::Torch | BlockKind::WallTorch => 0xffd36b,
+        // Tinted blocks are colored by the biome; this is only the fallback.
+        BlockKind::GrassBlock | BlockKind::Grass | BlockKind::TallGrass | BlockKind::Fern => 0x7cbd6b,
+        BlockKind::Water => 0x3f76e4,
+        _ => {
+            let hash = (kind as u32).wrapping_mul(2654435761);
+            let channel = |shift: u32| 0.35 + ((hash >> shift) & 0xff) as f32 / 255.0 * 0.45;
+            return Color::srgb(channel(0), channel(8), channel(16));
+        }
+    };
+    rgb(hex)
+}
@@ -1,10 +1,15 @@
+use bevy::log::{debug, error, info};
 use bevy::prelude::Resource;
 use tokio::sync::mpsc;
 use valence_protocol::{PacketDecoder, PacketEncoder, VarInt};
-use crate::events::ApplicationEvent;
-use crate::networking::handle_server_messages_inner;
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use valence_protocol::uuid::Uuid;
+use crate::auth::AuthConfig;
+use crate::error::NetworkError;
+use crate::events::{ApplicationEvent, ClientCommand};
+use crate::networking::{handle_server_messages_inner, send_packet};
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
 
 #[derive(Resource)]
 pub struct ConnectionStatus {
@@ -15,69 +20,135 @@ pub struct ConnectionStatus {
     pub(crate) encoder: Option<PacketEncoder>,
 }
 
+/// Protocol version this client speaks (1.20.1).
+pub(crate) const PROTOCOL_VERSION: i32 = 763;
+
+/// Port servers listen on when the address doesn't name one.
+const DEFAULT_PORT: u16 = 25565;
+
+/// Splits `host:port` for the handshake, defaulting to the standard port. IPv6 literals
+/// take a port only in brackets, as in `[::1]:25565`.
+pub(crate) fn split_host_port(address: &str) -> io::Result<(&str, u16)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid address {}", address));
+    let parse_port = |port: &str| -> io::Result<u16> { port.parse().map_err(|_| invalid()) };
+
+    if let Some(bracketed) = address.strip_prefix('[') {
+        let (host, rest) = bracketed.split_once(']').ok_or_else(invalid)?;
+        return match rest {
+            "" => Ok((host, DEFAULT_PORT)),
+            _ => Ok((host, parse_port(rest.strip_prefix(':').ok_or_else(invalid)?)?)),
+        };
+    }
+    match address.split_once(':') {
+        Some((_, rest)) if rest.contains(':') => Ok((address, DEFAULT_PORT)),
+        Some((host, port)) => Ok((host, parse_port(port)?)),
+        None => Ok((address, DEFAULT_PORT)),
+    }
+}
+
+/// Looks up the first socket address for a host and port from [`split_host_port`].
+pub(crate) fn resolve(host: &str, port: u16) -> io::Result<SocketAddr> {
+    (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no address for {}", host)))
+}
+
+/// Hands [`ClientCommand`]s to the connection task; empty while not connected.
+#[derive(Resource, Default)]
+pub(crate) struct ServerConnection {
+    pub(crate) sender: Option<mpsc::UnboundedSender<ClientCommand>>,
+}
+
+impl ServerConnection {
+    pub(crate) fn send(&self, command: ClientCommand) {
+        match &self.sender {
+            Some(sender) if sender.send(command).is_ok() => {}
+            _ => debug!("Not connected, dropping outgoing command"),
+        }
+    }
+}
+
+/// Connection health derived from keep-alive traffic, shown on the HUD.
+#[derive(Resource, Default)]
+pub struct NetworkStats {
+    /// Round-trip time as last reported by the server.
+    pub(crate) latency: Option<Duration>,
+    /// Time between the two most recent keep-alives.
+    pub(crate) keep_alive_interval: Option<Duration>,
+}
+
 pub(crate) async fn connect_and_handle(
     sender: mpsc::Sender<ApplicationEvent>,
     server_address: String,
+    username: String,
+    auth: AuthConfig,
+    commands: mpsc::UnboundedReceiver<ClientCommand>,
 ) {
-    match TcpStream::connect(&server_address) {
-        Ok(stream) => {
-            println!("Successfully connected to server at {}", server_address);
+    let reason = match connect_and_play(&sender, &server_address, &username, auth, commands).await {
+        Ok(()) => NetworkError::Closed,
+        Err(e) => e,
+    };
+    error!("Session with {} ended: {}", server_address, reason);
+    let _ = sender.send(ApplicationEvent::Disconnected(reason)).await; // Signal disconnection
+}
 
-            let _ = sender.send(ApplicationEvent::Connected).await;
+async fn connect_and_play(
+    sender: &mpsc::Sender<ApplicationEvent>,
+    server_address: &str,
+    username: &str,
+    auth: AuthConfig,
+    commands: mpsc::UnboundedReceiver<ClientCommand>,
+) -> Result<(), NetworkError> {
+    let (host, port) = split_host_port(server_address)?;
+    let stream = TcpStream::connect(resolve(host, port)?)?;
+    info!("Successfully connected to server at {}", server_address);
 
-            let mut connection_status = ConnectionStatus {
-                message: String::new(),
-                connected: true,
-                stream: Some(stream), // Store the connected stream
-                decoder: Some(PacketDecoder::new()),
-                encoder: Some(PacketEncoder::new()),
-            };
+    sender.send(ApplicationEvent::Connected).await?;
 
-            connect_to_server_inner(&mut connection_status); // Perform handshake/login
+    let mut connection_status = ConnectionStatus {
+        message: String::new(),
+        connected: true,
+        stream: Some(stream), // Store the connected stream
+        decoder: Some(PacketDecoder::new()),
+        encoder: Some(PacketEncoder::new()),
+    };
 
-            handle_server_messages_inner(&mut connection_status, sender.clone()).await;
+    connect_to_server_inner(&mut connection_status, server_address, username, auth.profile_id)?; // Perform handshake/login
 
-            let _ = sender.send(ApplicationEvent::Disconnected("Connection closed".to_string())).await; // Signal disconnection
-        }
-        Err(e) => {
-            println!("Failed to connect to server at {}: {}", server_address, e);
-            let _ = sender.send(ApplicationEvent::Disconnected(e.to_string())).await; // Signal disconnection with error
-        }
-    }
+    handle_server_messages_inner(&mut connection_status, sender.clone(), auth, commands).await
 }
 
 
-fn connect_to_server_inner(connection_status: &mut ConnectionStatus) {
-
-    let mut enc = connection_status.encoder.take().unwrap(); // Take encoder
+fn connect_to_server_inner(
+    connection_status: &mut ConnectionStatus,
+    server_address: &str,
+    username: &str,
+    profile_id: Option<Uuid>,
+) -> Result<(), NetworkError> {
+    let (Some(mut enc), Some(stream)) = (connection_status.encoder.take(), connection_status.stream.as_mut()) else {
+        return Err(NetworkError::Closed);
+    };
 
     // Handshake
+    let (host, port) = split_host_port(server_address)?;
     let next_state = valence_protocol::packets::handshaking::handshake_c2s::HandshakeNextState::Login;
     let handshake_packet = valence_protocol::packets::handshaking::handshake_c2s::HandshakeC2s {
-        protocol_version: VarInt(763),
-        server_address: valence_protocol::Bounded("127.0.0.1"), // Or use server_address variable
-        server_port: 25566,
+        protocol_version: VarInt(PROTOCOL_VERSION),
+        server_address: valence_protocol::Bounded(host),
+        server_port: port,
         next_state,
     };
-
-    enc.append_packet(&handshake_packet)
-        .expect("Failed to encode handshake packet");
-    connection_status.stream.as_mut().unwrap() // Use the existing stream
-        .write_all(&enc.take())
-        .expect("Failed to send handshake packet");
+    send_packet(&mut enc, stream, &handshake_packet)?;
 
     // Login
     let login_start_packet =
         valence_protocol::packets::login::login_hello_c2s::LoginHelloC2s {
-            username: valence_protocol::Bounded("ESP32-S3"), // Replace with your username
-            profile_id: None,                                // Optional in offline mode
+            username: valence_protocol::Bounded(username),
+            profile_id,                                      // Optional in offline mode
         };
-
-    enc.append_packet(&login_start_packet)
-        .expect("Failed to encode LoginHelloC2s packet");
-    connection_status.stream.as_mut().unwrap() // Use the existing stream
-        .write_all(&enc.take())
-        .expect("Failed to send handshake packet");
+    send_packet(&mut enc, stream, &login_start_packet)?;
 
     connection_status.encoder = Some(enc); // Put encoder back
+    Ok(())
 }
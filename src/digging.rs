@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use bevy::prelude::*;
+use valence_protocol::block::BlockKind;
+use valence_protocol::packets::play::player_action_c2s::PlayerAction;
+use valence_protocol::{BlockState, GameMode};
+use crate::blocks::BlockAppearances;
+use crate::connection::ServerConnection;
+use crate::events::ClientCommand;
+use crate::mesher::{face_uv, MeshBuilder, FACES};
+use crate::targeting::TargetedBlock;
+use crate::world::{ChunkStore, DimensionInfo};
+
+/// Crack stages run from 0 to this; the server clears a crack with any other value.
+const LAST_CRACK_STAGE: u8 = 9;
+/// Seconds between arm swings while digging, since vanilla keeps swinging too.
+const SWING_INTERVAL: f32 = 0.25;
+/// Vanilla waits this long after a block breaks before digging the next one.
+const DIG_DELAY: f32 = 0.25;
+/// Cracks are drawn just outside the block so they don't fight with its faces.
+const CRACK_SCALE: f32 = 1.002;
+/// Break progress is counted in game ticks, like vanilla's.
+const TICKS_PER_SECOND: f32 = 20.0;
+/// If the block is still there this long after we finished, the server didn't accept
+/// our timing and the dig starts over.
+const FINISH_TIMEOUT: f32 = 1.0;
+
+/// Crack stages from `BlockBreakingProgressS2c`, by the entity doing the breaking. The
+/// server doesn't send us our own, so these are only other players' cracks.
+#[derive(Resource, Default)]
+pub(crate) struct BreakingProgress(HashMap<i32, (IVec3, u8)>);
+
+impl BreakingProgress {
+    pub(crate) fn update(&mut self, breaker: i32, block: IVec3, stage: u8) {
+        if stage <= LAST_CRACK_STAGE {
+            self.0.insert(breaker, (block, stage));
+        } else {
+            self.0.remove(&breaker);
+        }
+    }
+}
+
+struct Dig {
+    block: IVec3,
+    face: usize,
+    swing_timer: f32,
+    /// Vanilla's destroy progress: the block is done at 1.
+    progress: f32,
+    /// Sent the finish packet, or needs none; all that's left is the block going away.
+    finished: bool,
+    /// Seconds since finishing with the block still there.
+    waited: f32,
+}
+
+/// The dig in progress. Like vanilla, we time it ourselves and tell the server once the
+/// block should be broken.
+#[derive(Resource, Default)]
+pub(crate) struct Digging {
+    current: Option<Dig>,
+    cooldown: f32,
+}
+
+impl Digging {
+    /// The crack on the block we're digging, counting up as vanilla's does.
+    fn crack(&self) -> Option<(IVec3, u8)> {
+        let dig = self.current.as_ref().filter(|dig| dig.progress > 0.0)?;
+        Some((dig.block, ((dig.progress * 10.0) as u8).min(LAST_CRACK_STAGE)))
+    }
+}
+
+/// Holding the left button digs the targeted block; letting go or looking away cancels it.
+pub(crate) fn dig_blocks(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    time: Res<Time>,
+    target: Res<TargetedBlock>,
+    dimension: Res<DimensionInfo>,
+    store: Res<ChunkStore>,
+    connection: Res<ServerConnection>,
+    mut digging: ResMut<Digging>,
+) {
+    let digging = &mut *digging;
+    digging.cooldown = (digging.cooldown - time.delta_seconds()).max(0.0);
+    let aimed = target.0.filter(|_| mouse_input.pressed(MouseButton::Left) && dimension.game_mode != GameMode::Spectator);
+
+    if let Some(dig) = digging.current.take() {
+        let broken = store.block(dig.block).map_or(true, |state| state.is_air());
+        if broken {
+            digging.cooldown = DIG_DELAY;
+        } else if dig.finished && dig.waited >= FINISH_TIMEOUT {
+            debug!("Block at {} wasn't broken, digging again", dig.block);
+        } else if aimed.map(|hit| hit.block) == Some(dig.block) {
+            digging.current = Some(dig);
+        } else if !dig.finished {
+            connection.send(ClientCommand::Dig { action: PlayerAction::AbortDestroyBlock, block: dig.block, face: dig.face });
+        }
+    }
+    let Some(hit) = aimed else {
+        return;
+    };
+
+    match &mut digging.current {
+        Some(dig) => {
+            dig.swing_timer -= time.delta_seconds();
+            if dig.swing_timer <= 0.0 {
+                dig.swing_timer = SWING_INTERVAL;
+                connection.send(ClientCommand::SwingArm);
+            }
+            if dig.finished {
+                dig.waited += time.delta_seconds();
+            } else {
+                let speed = store.block(dig.block).map_or(0.0, progress_per_tick);
+                dig.progress = (dig.progress + speed * TICKS_PER_SECOND * time.delta_seconds()).min(1.0);
+                if dig.progress >= 1.0 {
+                    dig.finished = true;
+                    connection.send(ClientCommand::Dig { action: PlayerAction::StopDestroyBlock, block: dig.block, face: dig.face });
+                }
+            }
+        }
+        None if digging.cooldown <= 0.0 => {
+            // Creative mode, and blocks without hardness, break as soon as we start.
+            let instant = dimension.game_mode == GameMode::Creative
+                || store.block(hit.block).is_some_and(|state| progress_per_tick(state) >= 1.0);
+            connection.send(ClientCommand::Dig { action: PlayerAction::StartDestroyBlock, block: hit.block, face: hit.face });
+            connection.send(ClientCommand::SwingArm);
+            digging.current = Some(Dig {
+                block: hit.block,
+                face: hit.face,
+                swing_timer: SWING_INTERVAL,
+                progress: 0.0,
+                finished: instant,
+                waited: 0.0,
+            });
+        }
+        None => {}
+    }
+}
+
+/// Vanilla's destroy progress per tick with an empty hand; blocks that need a tool to
+/// drop anything take over three times longer. We don't track the held item, so with a
+/// tool in hand the server would let us finish sooner than this.
+fn progress_per_tick(state: BlockState) -> f32 {
+    match hardness(state.to_kind()) {
+        (hardness, _) if hardness < 0.0 => 0.0,
+        (hardness, _) if hardness == 0.0 => 1.0,
+        (hardness, needs_tool) => 1.0 / hardness / if needs_tool { 100.0 } else { 30.0 },
+    }
+}
+
+/// Vanilla's hardness of a block, negative if it can't be broken, and whether it needs
+/// the right tool to drop anything. Matched by name, since families like ores, slabs and
+/// stairs share their values; anything else counts as hardness 1.
+fn hardness(kind: BlockKind) -> (f32, bool) {
+    let name = kind.to_str();
+    let stone_like = ["stone", "granite", "diorite", "andesite", "deepslate", "tuff", "brick", "purpur", "prismarine", "cobble"]
+        .iter()
+        .any(|part| name.contains(part));
+    match name {
+        "bedrock" | "barrier" | "light" | "command_block" | "chain_command_block" | "repeating_command_block"
+        | "structure_block" | "jigsaw" | "end_portal" | "end_portal_frame" | "end_gateway" | "nether_portal"
+        | "moving_piston" | "water" | "lava" | "bubble_column" => (-1.0, false),
+        // Plants, redstone components and the like break at once.
+        "redstone_wire" | "repeater" | "comparator" | "tripwire" | "tripwire_hook" | "fire" | "soul_fire" | "grass"
+        | "tall_grass" | "fern" | "large_fern" | "dead_bush" | "sugar_cane" | "lily_pad" | "wheat" | "carrots"
+        | "potatoes" | "beetroots" | "nether_wart" | "sweet_berry_bush" | "brown_mushroom" | "red_mushroom"
+        | "flower_pot" | "tnt" | "slime_block" | "honey_block" | "scaffolding" | "seagrass" | "tall_seagrass"
+        | "kelp" | "kelp_plant" | "dandelion" | "poppy" | "blue_orchid" | "allium" | "azure_bluet" | "oxeye_daisy"
+        | "cornflower" | "lily_of_the_valley" | "wither_rose" | "sunflower" | "lilac" | "rose_bush" | "peony" => (0.0, false),
+        _ if name.contains("torch") || name.ends_with("_sapling") || name.ends_with("_tulip") || name.starts_with("potted_") => {
+            (0.0, false)
+        }
+        "reinforced_deepslate" => (55.0, false),
+        "obsidian" | "crying_obsidian" | "netherite_block" | "respawn_anchor" => (50.0, true),
+        "ancient_debris" => (30.0, true),
+        "ender_chest" => (22.5, true),
+        "iron_block" | "diamond_block" | "emerald_block" | "redstone_block" | "anvil" | "chipped_anvil" | "damaged_anvil"
+        | "iron_door" | "iron_trapdoor" | "iron_bars" | "chain" | "bell" | "enchanting_table" | "spawner" | "coal_block"
+        | "raw_iron_block" | "raw_gold_block" | "raw_copper_block" => (5.0, true),
+        "cobweb" => (4.0, true),
+        "furnace" | "blast_furnace" | "smoker" | "dispenser" | "dropper" | "lantern" | "soul_lantern" | "stonecutter"
+        | "cobbled_deepslate" | "lodestone" => (3.5, true),
+        _ if name.starts_with("deepslate_") && name.ends_with("_ore") => (4.5, true),
+        _ if name.ends_with("_ore") => (3.0, true),
+        "gold_block" | "lapis_block" | "hopper" | "observer" | "deepslate" | "sculk_shrieker" | "sculk_catalyst"
+        | "lightning_rod" => (3.0, true),
+        "beacon" | "conduit" | "dragon_egg" => (3.0, false),
+        // Polished deepslate, its bricks and tiles, and their slabs, stairs and walls.
+        _ if name.contains("deepslate") => (3.5, true),
+        _ if name.starts_with("end_stone") => (3.0, true),
+        _ if name.contains("copper") => (3.0, true),
+        _ if name.ends_with("_door") || name.ends_with("_trapdoor") => (3.0, false),
+        "blue_ice" => (2.8, false),
+        "chest" | "trapped_chest" | "crafting_table" | "barrel" | "cartography_table" | "fletching_table"
+        | "smithing_table" | "loom" | "lectern" => (2.5, false),
+        "cauldron" | "water_cauldron" | "lava_cauldron" | "powder_snow_cauldron" | "grindstone" => (2.0, true),
+        "bookshelf" | "chiseled_bookshelf" | "piston" | "sticky_piston" | "sculk_sensor" | "calibrated_sculk_sensor" => {
+            (1.5, false)
+        }
+        "amethyst_block" | "budding_amethyst" => (1.5, true),
+        _ if name.contains("amethyst") => (1.5, false),
+        "glowstone" | "glass" | "glass_pane" | "tinted_glass" | "redstone_lamp" | "sea_lantern" => (0.3, false),
+        _ if name.ends_with("stained_glass") || name.ends_with("stained_glass_pane") => (0.3, false),
+        "snow" => (0.1, true),
+        "snow_block" => (0.2, true),
+        "powder_snow" => (0.25, false),
+        "netherrack" => (0.4, true),
+        "magma_block" => (0.5, true),
+        "calcite" => (0.75, true),
+        "basalt" | "polished_basalt" | "smooth_basalt" => (1.25, true),
+        _ if name.ends_with("_nylium") => (0.4, true),
+        _ if name.ends_with("_coral_block") => (1.5, true),
+        _ if name.contains("coral") => (0.0, false),
+        _ if name.ends_with("_concrete_powder") => (0.5, false),
+        _ if name.ends_with("_concrete") => (1.8, true),
+        _ if name.ends_with("glazed_terracotta") => (1.4, true),
+        _ if name.contains("terracotta") => (1.25, true),
+        _ if (name.contains("sandstone") || name.contains("quartz")) && (name.starts_with("smooth_") || name.ends_with("_slab")) => {
+            (2.0, true)
+        }
+        _ if name.contains("sandstone") || name.contains("quartz") => (0.8, true),
+        _ if name.ends_with("_button") => (0.5, false),
+        _ if name.ends_with("_pressure_plate") => (0.5, stone_like || name.contains("weighted")),
+        _ if stone_like && (name.ends_with("_slab") || name.ends_with("_stairs") || name.ends_with("_wall")) => (2.0, true),
+        "cobblestone" | "mossy_cobblestone" | "smooth_stone" | "bricks" | "bone_block" | "polished_blackstone" => (2.0, true),
+        _ if name.contains("nether_brick") => (2.0, true),
+        _ if stone_like => (1.5, true),
+        // What's left of slabs, stairs and fences is wooden.
+        _ if ["_planks", "_log", "_wood", "_stem", "_hyphae", "_fence", "_slab", "_stairs"].iter().any(|part| name.contains(part)) => {
+            (2.0, false)
+        }
+        "bamboo_block" | "stripped_bamboo_block" | "bamboo_mosaic" | "jukebox" | "campfire" | "soul_campfire" => (2.0, false),
+        _ if name.ends_with("shulker_box") => (2.0, false),
+        "pumpkin" | "carved_pumpkin" | "jack_o_lantern" | "melon" | "shroomlight" => (1.0, false),
+        _ if name.ends_with("_wool") || name == "note_block" => (0.8, false),
+        _ if name.ends_with("rail") => (0.7, false),
+        "dirt_path" => (0.65, false),
+        "grass_block" | "mycelium" | "farmland" | "gravel" | "clay" | "sponge" | "wet_sponge" | "honeycomb_block" => (0.6, false),
+        "dirt" | "coarse_dirt" | "podzol" | "rooted_dirt" | "mud" | "sand" | "red_sand" | "soul_sand" | "soul_soil" | "ice"
+        | "packed_ice" | "frosted_ice" | "hay_block" | "lever" | "cake" | "target" | "dried_kelp_block" => (0.5, false),
+        "cactus" | "ladder" => (0.4, false),
+        _ if name.ends_with("_leaves") || name.ends_with("_bed") || name.ends_with("_mushroom_block") => (0.2, false),
+        "vine" | "daylight_detector" | "cocoa" => (0.2, false),
+        _ if name.ends_with("_carpet") => (0.1, false),
+        _ => (1.0, false),
+    }
+}
+
+/// A crack drawn over a block someone is breaking.
+#[derive(Component)]
+pub(crate) struct CrackOverlay;
+
+#[derive(Resource)]
+pub(crate) struct CrackMaterial(Handle<StandardMaterial>);
+
+pub(crate) fn setup_crack_material(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+    let material = materials.add(StandardMaterial {
+        alpha_mode: AlphaMode::Multiply,
+        unlit: true,
+        ..default()
+    });
+    commands.insert_resource(CrackMaterial(material));
+}
+
+/// Redraws the cracks, ours and those the server reports for others, whenever one of
+/// them or the textures change.
+pub(crate) fn update_crack_overlays(
+    mut commands: Commands,
+    progress: Res<BreakingProgress>,
+    digging: Res<Digging>,
+    appearances: Res<BlockAppearances>,
+    crack_material: Res<CrackMaterial>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    overlay_query: Query<Entity, With<CrackOverlay>>,
+    mut drawn: Local<Vec<(IVec3, u8)>>,
+) {
+    let mut cracks: Vec<(IVec3, u8)> = progress.0.values().copied().chain(digging.crack()).collect();
+    // One crack per block, the furthest, when we're digging alongside someone else.
+    cracks.sort_by_key(|&(block, stage)| (block.to_array(), std::cmp::Reverse(stage)));
+    cracks.dedup_by_key(|(block, _)| *block);
+    if cracks == *drawn && !appearances.is_changed() {
+        return;
+    }
+    for entity in &overlay_query {
+        commands.entity(entity).despawn();
+    }
+    if let Some(material) = materials.get_mut(&crack_material.0) {
+        material.base_color_texture = appearances.atlas.clone();
+    }
+
+    for &(block, stage) in &cracks {
+        let Some(mesh) = crack_mesh(&appearances, stage) else {
+            continue;
+        };
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(mesh),
+                material: crack_material.0.clone(),
+                transform: Transform::from_translation(block.as_vec3() + Vec3::splat(0.5)),
+                ..default()
+            },
+            CrackOverlay,
+        ));
+    }
+    *drawn = cracks;
+}
+
+/// A cube around the origin with the crack texture of `stage` on every side. Without a
+/// resource pack the block just gets darker as the crack grows.
+fn crack_mesh(appearances: &BlockAppearances, stage: u8) -> Option<Mesh> {
+    let (rect, shade) = match appearances.destroy_stages[stage as usize] {
+        Some(rect) => (rect, 1.0),
+        None => (appearances.white, 1.0 - 0.05 * (stage as f32 + 1.0)),
+    };
+    let mut builder = MeshBuilder::default();
+    for face in &FACES {
+        builder.push_quad(
+            face.corners.map(|corner| corner.map(|v| (v - 0.5) * CRACK_SCALE)),
+            face.normal.as_vec3().to_array(),
+            face.corners.map(|corner| face_uv(face.normal, corner, rect)),
+            [[shade, shade, shade, 1.0]; 4],
+            false,
+        );
+    }
+    builder.build()
+}
@@ -0,0 +1,24 @@
+use std::collections::HashMap;
+use bevy::math::Vec3;
+use bevy::prelude::Resource;
+use valence_protocol::uuid::Uuid;
+
+/// `minecraft:player` in the 1.20.1 entity type registry. `PlayerSpawnS2c` doesn't carry
+/// a type, so players are filed under this id.
+pub(crate) const PLAYER_ENTITY_KIND: i32 = 122;
+/// `minecraft:lightning_bolt` in the same registry.
+pub(crate) const LIGHTNING_BOLT_KIND: i32 = 59;
+
+/// What we know about an entity the server told us about.
+#[derive(Clone, Debug)]
+pub(crate) struct TrackedEntity {
+    pub(crate) uuid: Uuid,
+    /// Network id in the entity type registry.
+    pub(crate) kind: i32,
+    /// Where it spawned; movement isn't tracked yet.
+    pub(crate) position: Vec3,
+}
+
+/// Entities in the current dimension, by the server's entity id.
+#[derive(Resource, Default)]
+pub(crate) struct EntityRegistry(pub(crate) HashMap<i32, TrackedEntity>);
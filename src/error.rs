@@ -0,0 +1,87 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use tokio::sync::mpsc::error::SendError;
+use valence_protocol::anyhow;
+use valence_protocol::PacketState;
+use crate::events::ApplicationEvent;
+
+/// Everything that can end a session. Carried to the UI by [`ApplicationEvent::Disconnected`].
+#[derive(Debug)]
+pub(crate) enum NetworkError {
+    /// Connecting, reading from or writing to the socket failed.
+    Io(io::Error),
+    /// A packet from the server could not be decoded.
+    Decode {
+        packet: &'static str,
+        source: anyhow::Error,
+    },
+    /// A packet for the server could not be encoded.
+    Encode {
+        packet: &'static str,
+        source: anyhow::Error,
+    },
+    /// The server sent a packet that makes no sense in the current protocol state.
+    UnexpectedPacket {
+        state: PacketState,
+        id: i32,
+    },
+    /// Online-mode login failed: bad key, missing credentials or the session server said no.
+    Auth(String),
+    /// The server kicked us, with the reason it gave.
+    Kicked(String),
+    /// The server closed the connection without saying why.
+    Closed,
+    /// The server went silent; no keep-alive arrived for this long.
+    TimedOut(Duration),
+    /// The Bevy side stopped listening for events.
+    EventChannelClosed,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Io(e) => write!(f, "I/O error: {}", e),
+            NetworkError::Decode { packet, source } => {
+                write!(f, "failed to decode {}: {}", packet, source)
+            }
+            NetworkError::Encode { packet, source } => {
+                write!(f, "failed to encode {}: {}", packet, source)
+            }
+            NetworkError::UnexpectedPacket { state, id } => {
+                write!(f, "unexpected packet 0x{:X} in {:?} state", id, state)
+            }
+            NetworkError::Auth(reason) => write!(f, "authentication failed: {}", reason),
+            NetworkError::Kicked(reason) => write!(f, "kicked by server: {}", reason),
+            NetworkError::Closed => write!(f, "connection closed by server"),
+            NetworkError::TimedOut(silence) => {
+                write!(f, "timed out, no keep-alive for {}s", silence.as_secs())
+            }
+            NetworkError::EventChannelClosed => write!(f, "client is shutting down"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NetworkError::Io(e) => Some(e),
+            NetworkError::Decode { source, .. } | NetworkError::Encode { source, .. } => {
+                Some(source.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NetworkError {
+    fn from(e: io::Error) -> Self {
+        NetworkError::Io(e)
+    }
+}
+
+impl From<SendError<ApplicationEvent>> for NetworkError {
+    fn from(_: SendError<ApplicationEvent>) -> Self {
+        NetworkError::EventChannelClosed
+    }
+}
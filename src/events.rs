@@ -1,9 +1,21 @@
-use valence_protocol::packets::play::ChunkDataS2c;
+use std::time::Duration;
+use bevy::math::{IVec2, IVec3, Vec3};
+use valence_protocol::packets::play::player_action_c2s::PlayerAction;
+use valence_protocol::packets::play::ResourcePackStatusC2s;
+use valence_protocol::{BlockState, GameMode, Hand};
+use crate::entities::TrackedEntity;
+use crate::error::NetworkError;
+use crate::options::ClientOptions;
+use crate::registry::Registries;
+use crate::server_pack::ResourcePackOffer;
+use crate::weather::WeatherChange;
+use crate::world::{ChunkSection, DimensionInfo, LightData};
 
 #[derive(Clone, Debug)]
 pub struct ChunkBlockData {
     pub pos: valence_protocol::ChunkPos,
-    pub blocks: Vec<u8>,
+    pub(crate) sections: Vec<ChunkSection>,
+    pub(crate) light: LightData,
 }
 
 
@@ -13,6 +25,79 @@ pub(crate) enum ApplicationEvent {
     LampOn,
     LampOff,
     ChatMessage(String),
-    Disconnected(String),
+    Disconnected(NetworkError),
+    /// A keep-alive was answered; `interval` is the time since the previous one.
+    KeepAlive { interval: Option<Duration> },
+    /// Round-trip time to the server, as last reported by the server.
+    Latency(Duration),
+    GameJoin {
+        dimension: DimensionInfo,
+        registries: Registries,
+    },
+    /// We moved to another dimension, or respawned; everything loaded so far is stale.
+    Respawn(DimensionInfo),
     ChunkData(ChunkBlockData),
+    UnloadChunk(IVec2),
+    LightUpdate {
+        pos: IVec2,
+        light: LightData,
+    },
+    BlockUpdates(Vec<(IVec3, BlockState)>),
+    /// The server placed us; `position` is at the feet.
+    PlayerPosition {
+        position: Vec3,
+        yaw: f32,
+        pitch: f32,
+    },
+    EntitySpawned {
+        id: i32,
+        entity: TrackedEntity,
+    },
+    EntitiesRemoved(Vec<i32>),
+    ResourcePackOffer(ResourcePackOffer),
+    /// A custom payload from the server, on any channel.
+    PluginMessage {
+        channel: String,
+        data: Vec<u8>,
+    },
+    /// `time_of_day` is negative while the daylight cycle is stopped.
+    WorldTime {
+        world_age: i64,
+        time_of_day: i64,
+    },
+    Weather(WeatherChange),
+    /// The server switched our game mode, e.g. after `/gamemode`.
+    GameModeChanged(GameMode),
+    /// Crack stage of a block `breaker` is digging; out of range when they stopped.
+    BreakingProgress {
+        breaker: i32,
+        block: IVec3,
+        stage: u8,
+    },
+}
+
+/// Requests from the game for the connection task to send something to the server.
+#[derive(Debug)]
+pub(crate) enum ClientCommand {
+    ResourcePackStatus(ResourcePackStatusC2s),
+    ClientSettings(ClientOptions),
+    /// A custom payload on a plugin channel like `minecraft:brand`.
+    PluginMessage {
+        channel: String,
+        data: Vec<u8>,
+    },
+    /// Starts, cancels or finishes digging `block` from its `face`, indexed like the mesher's faces.
+    Dig {
+        action: PlayerAction,
+        block: IVec3,
+        face: usize,
+    },
+    SwingArm,
+    /// Right-clicks `face` of `block` with what's in `hand`; `cursor` is where, within the block.
+    UseBlock {
+        hand: Hand,
+        block: IVec3,
+        face: usize,
+        cursor: Vec3,
+    },
 }
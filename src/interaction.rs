@@ -0,0 +1,41 @@
+use bevy::prelude::*;
+use valence_protocol::{GameMode, Hand};
+use crate::connection::ServerConnection;
+use crate::events::ClientCommand;
+use crate::targeting::TargetedBlock;
+use crate::world::DimensionInfo;
+
+/// Seconds between uses while the right button is held, like vanilla's 4 tick delay.
+const USE_DELAY: f32 = 0.2;
+
+/// Right-clicking uses the targeted block: flips levers, presses buttons, opens doors,
+/// or places whatever is in the main hand against the clicked face. The server decides
+/// which; we see the outcome in its block updates.
+pub(crate) fn use_blocks(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    time: Res<Time>,
+    target: Res<TargetedBlock>,
+    dimension: Res<DimensionInfo>,
+    connection: Res<ServerConnection>,
+    mut cooldown: Local<f32>,
+) {
+    *cooldown = (*cooldown - time.delta_seconds()).max(0.0);
+    if !mouse_input.pressed(MouseButton::Right) || dimension.game_mode == GameMode::Spectator {
+        return;
+    }
+    if !mouse_input.just_pressed(MouseButton::Right) && *cooldown > 0.0 {
+        return;
+    }
+    let Some(hit) = target.0 else {
+        return;
+    };
+
+    *cooldown = USE_DELAY;
+    connection.send(ClientCommand::UseBlock {
+        hand: Hand::Main,
+        block: hit.block,
+        face: hit.face,
+        cursor: hit.point - hit.block.as_vec3(),
+    });
+    connection.send(ClientCommand::SwingArm);
+}
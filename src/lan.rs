@@ -0,0 +1,191 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+use bevy::prelude::*;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::sync::mpsc;
+
+/// Vanilla clients announce "Open to LAN" worlds to this group every 1.5 seconds.
+const LAN_MULTICAST_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 2, 60);
+const LAN_MULTICAST_PORT: u16 = 4445;
+/// A world that hasn't been announced for this long is dropped from the list.
+const LAN_SERVER_EXPIRY: Duration = Duration::from_secs(5);
+
+pub(crate) struct LanServer {
+    pub(crate) motd: String,
+    /// `host:port` to connect to.
+    pub(crate) address: String,
+    last_seen: Instant,
+}
+
+/// Worlds currently announced on the local network.
+#[derive(Resource, Default)]
+pub(crate) struct LanServers(pub(crate) Vec<LanServer>);
+
+#[derive(Resource)]
+pub(crate) struct LanChannel {
+    sender: mpsc::Sender<(String, String)>,
+    receiver: mpsc::Receiver<(String, String)>,
+}
+
+impl Default for LanChannel {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel(32);
+        Self { sender, receiver }
+    }
+}
+
+/// Listens on a thread of its own: `recv_from` blocks until the next announcement, which
+/// would tie up a task pool thread that pings and the connection need.
+pub(crate) fn start_lan_discovery(channel: Res<LanChannel>) {
+    let sender = channel.sender.clone();
+
+    let spawned = thread::Builder::new().name("lan-discovery".to_string()).spawn(move || {
+        match join_lan_group(LAN_MULTICAST_PORT) {
+            Ok(socket) => {
+                info!("Listening for LAN worlds on {}:{}", LAN_MULTICAST_GROUP, LAN_MULTICAST_PORT);
+                listen_for_announcements(socket, sender);
+            }
+            Err(e) => {
+                warn!("LAN discovery disabled, can't listen on {}:{}: {}", LAN_MULTICAST_GROUP, LAN_MULTICAST_PORT, e);
+            }
+        }
+    });
+    if let Err(e) = spawned {
+        warn!("LAN discovery disabled, can't start its thread: {}", e);
+    }
+}
+
+/// Forwards every announcement received on `socket` until it fails or nobody listens.
+fn listen_for_announcements(socket: UdpSocket, sender: mpsc::Sender<(String, String)>) {
+    let mut buffer = [0; 1024];
+    loop {
+        let (size, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("LAN discovery stopped: {}", e);
+                return;
+            }
+        };
+        let message = String::from_utf8_lossy(&buffer[..size]);
+        let Some((motd, port)) = parse_announcement(&message) else {
+            debug!("Ignoring LAN packet from {}: {:?}", from, message);
+            continue;
+        };
+        let address = lan_server_address(from, port);
+        trace!("LAN world {:?} at {}", motd, address);
+        if sender.blocking_send((motd, address)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Binds with `SO_REUSEADDR` so other clients on this machine can listen on the port too.
+fn join_lan_group(port: u16) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    let socket = UdpSocket::from(socket);
+    socket.join_multicast_v4(&LAN_MULTICAST_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    Ok(socket)
+}
+
+/// The announcement only carries a port; the world is hosted wherever it came from.
+fn lan_server_address(from: SocketAddr, port: u16) -> String {
+    SocketAddr::new(from.ip(), port).to_string()
+}
+
+/// Parses `[MOTD]<motd>[/MOTD][AD]<port>[/AD]` into the MOTD and port.
+fn parse_announcement(message: &str) -> Option<(String, u16)> {
+    let motd = between(message, "[MOTD]", "[/MOTD]")?;
+    let port = between(message, "[AD]", "[/AD]")?.trim().parse().ok()?;
+    Some((motd.to_string(), port))
+}
+
+fn between<'a>(text: &'a str, open: &str, close: &str) -> Option<&'a str> {
+    let start = text.find(open)? + open.len();
+    let end = start + text[start..].find(close)?;
+    Some(&text[start..end])
+}
+
+pub(crate) fn receive_lan_announcements(mut channel: ResMut<LanChannel>, mut lan_servers: ResMut<LanServers>) {
+    // Announcements repeat constantly; only flag the resource as changed when the list
+    // actually changes so the menu isn't rebuilt every 1.5 seconds.
+    let mut changed = false;
+    let servers = &mut lan_servers.bypass_change_detection().0;
+
+    while let Ok((motd, address)) = channel.receiver.try_recv() {
+        match servers.iter_mut().find(|server| server.address == address) {
+            Some(server) => {
+                server.last_seen = Instant::now();
+                if server.motd != motd {
+                    server.motd = motd;
+                    changed = true;
+                }
+            }
+            None => {
+                info!("Discovered LAN world {:?} at {}", motd, address);
+                servers.push(LanServer {
+                    motd,
+                    address,
+                    last_seen: Instant::now(),
+                });
+                changed = true;
+            }
+        }
+    }
+
+    let before = servers.len();
+    servers.retain(|server| server.last_seen.elapsed() <= LAN_SERVER_EXPIRY);
+    changed |= servers.len() != before;
+
+    if changed {
+        lan_servers.set_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_vanilla_announcement() {
+        let parsed = parse_announcement("[MOTD]Steve - New World[/MOTD][AD]51234[/AD]");
+        assert_eq!(parsed, Some(("Steve - New World".to_string(), 51234)));
+    }
+
+    #[test]
+    fn rejects_incomplete_announcements() {
+        assert_eq!(parse_announcement("[MOTD]World[/MOTD]"), None);
+        assert_eq!(parse_announcement("[MOTD]World[/MOTD][AD]not a port[/AD]"), None);
+        assert_eq!(parse_announcement("[MOTD]World[AD]25565[/AD]"), None);
+    }
+
+    #[test]
+    fn joins_sender_ip_with_announced_port() {
+        let from: SocketAddr = "192.168.1.20:4445".parse().unwrap();
+        assert_eq!(lan_server_address(from, 51234), "192.168.1.20:51234");
+    }
+
+    #[test]
+    fn receives_announcement_from_local_sender() {
+        let listener = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut channel = LanChannel::default();
+        let sender = channel.sender.clone();
+        thread::spawn(move || listen_for_announcements(listener, sender));
+
+        let announcer = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        announcer.send_to(b"[MOTD]Alex - Test World[/MOTD][AD]51234[/AD]", (Ipv4Addr::LOCALHOST, port)).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let received = loop {
+            if let Ok(received) = channel.receiver.try_recv() {
+                break received;
+            }
+            assert!(Instant::now() < deadline, "no announcement arrived");
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(received, ("Alex - Test World".to_string(), "127.0.0.1:51234".to_string()));
+    }
+}
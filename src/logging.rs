@@ -0,0 +1,40 @@
+use std::fs::File;
+use std::sync::Mutex;
+use bevy::log::{BoxedLayer, Level, LogPlugin};
+use bevy::prelude::App;
+use tracing_subscriber::fmt;
+use tracing_subscriber::Layer;
+
+/// When set, every log record is additionally written as JSON lines to this file.
+pub(crate) const LOG_JSON_FILE_ENV: &str = "BEVY_CLIENT_LOG_JSON";
+
+/// Log plugin used instead of Bevy's default one.
+///
+/// Each module logs under its own target (`bevy_client::connection`, `bevy_client::networking`,
+/// `bevy_client::world`, `bevy_client::rendering`), so `RUST_LOG` can narrow things down, e.g.
+/// `RUST_LOG=info,bevy_client::networking=trace` to see every packet.
+pub(crate) fn log_plugin() -> LogPlugin {
+    LogPlugin {
+        filter: "wgpu=error,naga=warn".to_string(),
+        level: Level::INFO,
+        custom_layer: json_file_layer,
+    }
+}
+
+fn json_file_layer(_app: &mut App) -> Option<BoxedLayer> {
+    let path = std::env::var_os(LOG_JSON_FILE_ENV)?;
+
+    match File::create(&path) {
+        Ok(file) => Some(
+            fmt::layer()
+                .json()
+                .with_writer(Mutex::new(file))
+                .boxed(),
+        ),
+        Err(e) => {
+            // The subscriber isn't installed yet, so this can't go through `tracing`.
+            eprintln!("Failed to open JSON log file {:?}: {}", path, e);
+            None
+        }
+    }
+}
@@ -4,22 +4,50 @@ mod events;
 mod rendering;
 mod world;
 mod controls;
-
-use valence_protocol::block::{PropName, PropValue};
-use valence_protocol::packets::play::BlockUpdateS2c;
+mod error;
+mod logging;
+mod status;
+mod menu;
+mod lan;
+mod auth;
+mod registry;
+mod mesher;
+mod entities;
+mod blocks;
+mod resource_pack;
+mod server_pack;
+mod block_models;
+mod sky;
+mod weather;
+mod options;
+mod plugin_channels;
+mod targeting;
+mod digging;
+mod interaction;
+
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use std::io::{Read, Write};
-use std::net::TcpStream;
-use bevy::render::view::NoFrustumCulling;
 use bevy::tasks::AsyncComputeTaskPool;
 use tokio::sync::mpsc;
-use valence_protocol::{PacketDecoder, PacketEncoder, VarInt};
-use valence_protocol::Packet;
 
-use connection::{connect_and_handle, ConnectionStatus};
+use connection::{connect_and_handle, ConnectionStatus, NetworkStats, ServerConnection};
 use events::ApplicationEvent;
-use crate::rendering::setup_ui;
+use crate::blocks::load_block_appearances;
+use crate::entities::{EntityRegistry, LIGHTNING_BOLT_KIND};
+use crate::server_pack::{handle_pack_prompt_buttons, receive_downloaded_packs, update_pack_prompt, PackDownloadChannel, PendingPackOffer};
+use crate::rendering::{setup_ui, animate_fluids, update_chunk_meshes, update_view_distance, update_loading_screen, update_network_hud, LoadingTerrain, SectionEntities, StatusText};
+use crate::registry::Registries;
+use crate::sky::{setup_sky, update_sky, WorldTime};
+use crate::options::{adjust_render_distance, send_client_settings, send_join_info, ClientOptions};
+use crate::plugin_channels::{log_plugin_messages, register_new_channels, send_plugin_messages, PluginChannels, PluginMessageInbox, PluginMessageReceived, SendPluginMessage};
+use crate::weather::{setup_precipitation, update_weather, Weather};
+use crate::targeting::{draw_target_outline, setup_target_hud, update_target_hud, update_targeted_block, TargetedBlock};
+use crate::digging::{dig_blocks, setup_crack_material, update_crack_overlays, BreakingProgress, Digging};
+use crate::interaction::use_blocks;
+use crate::world::{Chunk, ChunkStore, DimensionInfo};
 use controls::handle_keyboard_input;
+use lan::{receive_lan_announcements, start_lan_discovery, LanChannel, LanServers};
+use menu::{despawn_menu, handle_menu_buttons, handle_text_input, open_menu, rebuild_menu, receive_pings, MenuState, PingChannel, SavedServers, ServerPings};
 
 
 #[derive(Resource)]
@@ -34,23 +62,44 @@ struct ConnectionTask;
 #[derive(Resource)]
 struct ServerAddress(String);
 
-#[derive(Component)]
-struct WorldCube {
-    x: usize,
-    y: usize,
-    z: usize,
-}
+#[derive(Resource)]
+struct Username(String);
+
+/// Camera height above the player's feet.
+const PLAYER_EYE_HEIGHT: f32 = 1.62;
 
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+enum AppState {
+    /// Server browser.
+    #[default]
+    Menu,
+    InGame,
+}
 
 #[derive(Component)]
 struct GlowingCube;
 
 fn main() {
-    // env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    // App::new().add_systems(Startup, connect_to_server).run();
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--ping") {
+        let address = args.get(index + 1).map_or("127.0.0.1:25565", String::as_str);
+        std::process::exit(status::ping_command(address));
+    }
+
     let (sender, receiver) = mpsc::channel(32);
+    let saved_servers = SavedServers::load();
     App::new()
         .insert_resource(ServerAddress("127.0.0.1:25565".to_string()))
+        .insert_resource(Username(saved_servers.username.clone()))
+        .insert_resource(auth::AuthConfig::from_env())
+        .insert_resource(saved_servers)
+        .insert_resource(ClientOptions::load())
+        .insert_resource(PluginChannels::from_env())
+        .init_resource::<MenuState>()
+        .init_resource::<ServerPings>()
+        .init_resource::<PingChannel>()
+        .init_resource::<LanServers>()
+        .init_resource::<LanChannel>()
         .insert_resource(ConnectionStatus {
             message: "Connecting...".to_string(),
             connected: false,
@@ -58,15 +107,61 @@ fn main() {
             decoder: None,
             encoder: None,
         })
+        .insert_resource(NetworkStats::default())
+        .init_resource::<DimensionInfo>()
+        .init_resource::<Registries>()
+        .init_resource::<ChunkStore>()
+        .init_resource::<SectionEntities>()
+        .init_resource::<EntityRegistry>()
+        .init_resource::<LoadingTerrain>()
+        .init_resource::<WorldTime>()
+        .init_resource::<Weather>()
+        .init_resource::<BreakingProgress>()
+        .init_resource::<TargetedBlock>()
+        .init_resource::<Digging>()
+        .init_resource::<ServerConnection>()
+        .init_resource::<PendingPackOffer>()
+        .init_resource::<PackDownloadChannel>()
         .insert_resource(ConnectionEventChannel {
             sender,
             receiver,
         })
-        .add_plugins(DefaultPlugins)
-        .add_systems(Startup, setup_ui)
-        .add_systems(Startup, start_connection_task)
+        .add_plugins(DefaultPlugins.set(logging::log_plugin()))
+        .add_event::<PluginMessageReceived>()
+        .add_event::<SendPluginMessage>()
+        .init_state::<AppState>()
+        .add_systems(Startup, (load_block_appearances, setup_ui).chain())
+        .add_systems(Startup, start_lan_discovery)
+        .add_systems(Startup, (setup_sky, setup_precipitation, setup_crack_material, setup_target_hud))
+        .add_systems(OnEnter(AppState::Menu), open_menu)
+        .add_systems(Update, (receive_pings, receive_lan_announcements, handle_menu_buttons, handle_text_input, rebuild_menu).chain().run_if(in_state(AppState::Menu)))
+        .add_systems(OnExit(AppState::Menu), despawn_menu)
+        .add_systems(OnEnter(AppState::InGame), start_connection_task)
         .add_systems(Update, process_application_event)
-        .add_systems(Update, handle_keyboard_input)
+        .add_systems(Update, handle_keyboard_input.run_if(in_state(AppState::InGame)))
+        .add_systems(Update, update_network_hud)
+        .add_systems(Update, update_chunk_meshes.after(process_application_event))
+        .add_systems(Update, animate_fluids)
+        .add_systems(
+            Update,
+            (update_targeted_block, (draw_target_outline, update_target_hud, dig_blocks, use_blocks))
+                .chain()
+                .after(handle_keyboard_input)
+                .after(process_application_event)
+                .run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(Update, update_crack_overlays.after(process_application_event))
+        .add_systems(
+            Update,
+            (adjust_render_distance, send_client_settings, update_view_distance).chain().run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(
+            Update,
+            (update_weather, update_sky).chain().after(process_application_event).run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(Update, update_loading_screen.after(process_application_event))
+        .add_systems(Update, (register_new_channels, log_plugin_messages, send_plugin_messages).chain().after(process_application_event))
+        .add_systems(Update, (update_pack_prompt, handle_pack_prompt_buttons, receive_downloaded_packs).chain().after(process_application_event))
         .run();
 }
 
@@ -82,120 +177,199 @@ fn start_connection_task(
     mut commands: Commands,
     event_sender: Res<ConnectionEventChannel>,
     server_address: Res<ServerAddress>,
+    username: Res<Username>,
+    auth: Res<auth::AuthConfig>,
+    mut server_connection: ResMut<ServerConnection>,
 ) {
-    println!("Starting connection task...");
+    info!("Starting connection task...");
     let sender = event_sender.sender.clone();
     let address = server_address.0.clone();
+    let username = username.0.clone();
+    let auth = auth.clone();
+    let (command_sender, command_receiver) = mpsc::unbounded_channel();
+    server_connection.sender = Some(command_sender);
 
     commands.spawn(ConnectionTask); // You can still spawn an entity if needed
 
     let async_compute_task_pool = AsyncComputeTaskPool::get();
 
     async_compute_task_pool.spawn(async move {
-        connect_and_handle(sender, address).await;
+        connect_and_handle(sender, address, username, auth, command_receiver).await;
     }).detach();
 
 }
 
+/// Everything the client knows about the world it's in.
+#[derive(SystemParam)]
+struct GameWorld<'w> {
+    dimension_info: ResMut<'w, DimensionInfo>,
+    registries: ResMut<'w, Registries>,
+    chunk_store: ResMut<'w, ChunkStore>,
+    section_entities: ResMut<'w, SectionEntities>,
+    entity_registry: ResMut<'w, EntityRegistry>,
+    loading_terrain: ResMut<'w, LoadingTerrain>,
+    world_time: ResMut<'w, WorldTime>,
+    weather: ResMut<'w, Weather>,
+    breaking_progress: ResMut<'w, BreakingProgress>,
+}
+
+impl GameWorld<'_> {
+    /// Throws away everything from the previous world and shows the loading screen until
+    /// the new one arrives.
+    fn enter(&mut self, commands: &mut Commands, dimension: DimensionInfo) {
+        info!("Entering {}, clearing {} entities", dimension.dimension_name, self.entity_registry.0.len());
+        self.chunk_store.reset(dimension.min_y);
+        self.section_entities.clear(commands);
+        self.entity_registry.0.clear();
+        *self.loading_terrain = LoadingTerrain { active: true, spawn: None };
+        // The server sends the new dimension's weather after the respawn.
+        *self.weather = Weather::default();
+        *self.breaking_progress = BreakingProgress::default();
+        *self.dimension_info = dimension;
+    }
+}
+
 fn process_application_event(
+    mut commands: Commands,
     mut connection_status: ResMut<ConnectionStatus>,
-    mut text_query: Query<&mut Text>, // Renamed for clarity
+    mut network_stats: ResMut<NetworkStats>,
+    mut world: GameWorld,
+    mut server_connection: ResMut<ServerConnection>,
+    mut pending_pack: ResMut<PendingPackOffer>,
+    options: Res<ClientOptions>,
+    mut plugin_inbox: PluginMessageInbox,
+    mut camera_query: Query<&mut Transform, With<Camera3d>>,
+    mut text_query: Query<&mut Text, With<StatusText>>,
     mut event_receiver: ResMut<ConnectionEventChannel>,
-    mut material_query: Query<&mut Handle<StandardMaterial>, With<GlowingCube>>, // Query for material
+    mut menu_state: ResMut<MenuState>,
+    mut next_state: ResMut<NextState<AppState>>,
+    material_query: Query<&Handle<StandardMaterial>, With<GlowingCube>>, // Query for material
     mut materials: ResMut<Assets<StandardMaterial>>, // Access to materials
 ) {
     while let Ok(event) = event_receiver.receiver.try_recv() {
-        println!("Received event: {:?}", event);
+        debug!("Received event: {:?}", event);
         match event {
             ApplicationEvent::Connected => {
                 connection_status.message = "Connected!".to_string();
                 connection_status.connected = true;
             }
             ApplicationEvent::ChatMessage(message) => {
-                println!("** Received ChatMessage: {}", message);
+                info!("Received ChatMessage: {}", message);
                 connection_status.message = message;
             }
             ApplicationEvent::Disconnected(reason) => {
-                connection_status.message = format!("Connection failed: {}", reason);
+                connection_status.message = format!("Disconnected: {}", reason);
                 connection_status.connected = false;
                 connection_status.stream = None;
                 connection_status.decoder = None;
                 connection_status.encoder = None;
+                *network_stats = NetworkStats::default();
+                *world.loading_terrain = LoadingTerrain::default();
+                server_connection.sender = None;
+                pending_pack.0 = None;
+                menu_state.error = Some(connection_status.message.clone());
+                next_state.set(AppState::Menu);
+            }
+            ApplicationEvent::KeepAlive { interval } => {
+                if interval.is_some() {
+                    network_stats.keep_alive_interval = interval;
+                }
+            }
+            ApplicationEvent::Latency(latency) => {
+                network_stats.latency = Some(latency);
             }
             ApplicationEvent::LampOn => {
-                if let Ok(mut material_handle) = material_query.get_single_mut() {
-                    let mut material = materials.get_mut(&*material_handle).unwrap();
-                    material.base_color = Color::rgb(1.0, 1.0, 0.0);
+                if let Ok(material_handle) = material_query.get_single() {
+                    if let Some(material) = materials.get_mut(material_handle) {
+                        material.base_color = Color::rgb(1.0, 1.0, 0.0);
+                    }
                 }
             }
             ApplicationEvent::LampOff => {
-                if let Ok(mut material_handle) = material_query.get_single_mut() {
-                    let mut material = materials.get_mut(&*material_handle).unwrap();
-                    material.base_color = Color::rgb(0.0, 1.0, 0.0);
+                if let Ok(material_handle) = material_query.get_single() {
+                    if let Some(material) = materials.get_mut(material_handle) {
+                        material.base_color = Color::rgb(0.0, 1.0, 0.0);
+                    }
                 }
             }
+            ApplicationEvent::GameJoin { dimension, registries } => {
+                world.enter(&mut commands, dimension);
+                *world.registries = registries;
+                *world.world_time = WorldTime::default();
+                send_join_info(&server_connection, &options);
+                plugin_inbox.register();
+            }
+            ApplicationEvent::Respawn(dimension) => {
+                if dimension.game_mode != world.dimension_info.game_mode {
+                    info!("Game mode changed to {:?}", dimension.game_mode);
+                }
+                world.enter(&mut commands, dimension);
+            }
             ApplicationEvent::ChunkData(data) => {
-
+                let pos = IVec2::new(data.pos.x, data.pos.z);
+                let chunk = Chunk::new(data.sections, data.light, world.dimension_info.has_skylight);
+                world.chunk_store.insert_chunk(pos, chunk);
+            }
+            ApplicationEvent::UnloadChunk(pos) => {
+                world.chunk_store.remove_chunk(pos);
+            }
+            ApplicationEvent::LightUpdate { pos, light } => {
+                world.chunk_store.update_light(pos, light);
+            }
+            ApplicationEvent::BlockUpdates(updates) => {
+                for (position, state) in updates {
+                    world.chunk_store.set_block(position, state);
+                }
+            }
+            ApplicationEvent::PlayerPosition { position, yaw, pitch } => {
+                // Minecraft yaw 0 faces +Z and turns clockwise seen from above.
+                let (yaw, pitch) = (yaw.to_radians(), pitch.to_radians());
+                let direction = Vec3::new(-yaw.sin() * pitch.cos(), -pitch.sin(), yaw.cos() * pitch.cos());
+                if world.loading_terrain.active {
+                    world.loading_terrain.spawn = Some(position);
+                }
+                if let Ok(mut camera_transform) = camera_query.get_single_mut() {
+                    *camera_transform = Transform::from_translation(position + Vec3::Y * PLAYER_EYE_HEIGHT)
+                        .looking_to(direction, Vec3::Y);
+                }
+            }
+            ApplicationEvent::EntitySpawned { id, entity } => {
+                if entity.kind == LIGHTNING_BOLT_KIND {
+                    world.weather.strike_lightning();
+                }
+                world.entity_registry.0.insert(id, entity);
+            }
+            ApplicationEvent::EntitiesRemoved(ids) => {
+                for id in ids {
+                    world.entity_registry.0.remove(&id);
+                }
+            }
+            ApplicationEvent::ResourcePackOffer(offer) => {
+                pending_pack.0 = Some(offer);
+            }
+            ApplicationEvent::WorldTime { world_age, time_of_day } => {
+                world.world_time.update(world_age, time_of_day);
+            }
+            ApplicationEvent::Weather(change) => {
+                world.weather.apply(change);
+            }
+            ApplicationEvent::GameModeChanged(game_mode) => {
+                info!("Game mode changed to {:?}", game_mode);
+                world.dimension_info.game_mode = game_mode;
+            }
+            ApplicationEvent::BreakingProgress { breaker, block, stage } => {
+                world.breaking_progress.update(breaker, block, stage);
+            }
+            ApplicationEvent::PluginMessage { channel, data } => {
+                plugin_inbox.deliver(channel, data);
             }
         }
     }
 
-    let mut text = text_query.single_mut(); // Use renamed query
+    let mut text = text_query.single_mut();
     if text.sections[0].value != connection_status.message {
-        println!("Connection status message updated: {}", connection_status.message);
+        debug!("Connection status message updated: {}", connection_status.message);
         text.sections[0].value = connection_status.message.clone();
     }
 }
-fn connect_to_server(connection_status: &mut ConnectionStatus) {
-    let server_address = "127.0.0.1:25565";
-
-    match TcpStream::connect(server_address) {
-        Ok(stream) => {
-            println!("Successfully connected to server at {}", server_address);
-            connection_status.message = "Connected!".to_string();
-            connection_status.connected = true;
-            connection_status.stream = Some(stream.try_clone().unwrap()); // Clone the stream
-            connection_status.decoder = Some(PacketDecoder::new());
-            connection_status.encoder = Some(PacketEncoder::new());
-
-            // Perform handshake and login here (same as before)
-            let mut enc = connection_status.encoder.take().unwrap();
-
-            // Handshake
-            let next_state = valence_protocol::packets::handshaking::handshake_c2s::HandshakeNextState::Login;
-            let handshake_packet = valence_protocol::packets::handshaking::handshake_c2s::HandshakeC2s {
-                protocol_version: VarInt(763),
-                server_address: valence_protocol::Bounded("127.0.0.1"),
-                server_port: 25566,
-                next_state,
-            };
-
-            enc.append_packet(&handshake_packet)
-                .expect("Failed to encode handshake packet");
-            connection_status.stream.as_mut().unwrap()
-                .write_all(&enc.take())
-                .expect("Failed to send handshake packet");
-
-            // Login
-            let login_start_packet =
-                valence_protocol::packets::login::login_hello_c2s::LoginHelloC2s {
-                    username: valence_protocol::Bounded("ESP32-S3"), // Replace with your username
-                    profile_id: None,                                // Optional in offline mode
-                };
-
-            enc.append_packet(&login_start_packet)
-                .expect("Failed to encode LoginHelloC2s packet");
-            connection_status.stream.as_mut().unwrap()
-                .write_all(&enc.take())
-                .expect("Failed to send handshake packet");
-
-            connection_status.encoder = Some(enc);
-        }
-        Err(e) => {
-            println!("Failed to connect to server at {}: {}", server_address, e);
-            connection_status.message = format!("Connection failed: {}", e);
-            connection_status.connected = false;
-        }
-    }
-}
 
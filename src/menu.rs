@@ -0,0 +1,604 @@
+use std::collections::HashMap;
+use std::fs;
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::texture::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::tasks::AsyncComputeTaskPool;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use crate::lan::LanServers;
+use crate::status::{query_status, ServerStatus};
+use crate::{AppState, ServerAddress, Username};
+
+/// Saved servers and the username, kept next to the binary's working directory.
+const SERVER_LIST_FILE: &str = "servers.json";
+
+const BACKGROUND: Color = Color::srgb(0.08, 0.08, 0.1);
+const ROW: Color = Color::srgb(0.15, 0.15, 0.18);
+const ROW_SELECTED: Color = Color::srgb(0.25, 0.3, 0.4);
+const BUTTON: Color = Color::srgb(0.2, 0.22, 0.28);
+const FIELD: Color = Color::srgb(0.05, 0.05, 0.05);
+const FIELD_FOCUSED: Color = Color::srgb(0.9, 0.8, 0.3);
+const DIM_TEXT: Color = Color::srgb(0.6, 0.6, 0.6);
+const ERROR_TEXT: Color = Color::srgb(1.0, 0.4, 0.4);
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct SavedServer {
+    pub(crate) name: String,
+    pub(crate) address: String,
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+pub(crate) struct SavedServers {
+    pub(crate) username: String,
+    pub(crate) servers: Vec<SavedServer>,
+}
+
+impl Default for SavedServers {
+    fn default() -> Self {
+        Self {
+            username: "ESP32-S3".to_string(),
+            servers: vec![SavedServer {
+                name: "Local server".to_string(),
+                address: "127.0.0.1:25565".to_string(),
+            }],
+        }
+    }
+}
+
+impl SavedServers {
+    pub(crate) fn load() -> Self {
+        let Ok(json) = fs::read_to_string(SERVER_LIST_FILE) else {
+            return Self::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable {}: {}", SERVER_LIST_FILE, e);
+            Self::default()
+        })
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(SERVER_LIST_FILE, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Failed to save {}: {}", SERVER_LIST_FILE, e);
+        }
+    }
+}
+
+enum PingResult {
+    Pending,
+    Online {
+        status: ServerStatus,
+        favicon: Option<Handle<Image>>,
+    },
+    Offline(String),
+}
+
+/// Latest status query result per server address.
+#[derive(Resource, Default)]
+pub(crate) struct ServerPings {
+    results: HashMap<String, PingResult>,
+    /// One image per address, overwritten on each refresh instead of adding a new one.
+    favicons: HashMap<String, Handle<Image>>,
+}
+
+impl ServerPings {
+    fn remove(&mut self, address: &str, images: &mut Assets<Image>) {
+        self.results.remove(address);
+        if let Some(favicon) = self.favicons.remove(address) {
+            images.remove(&favicon);
+        }
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct PingChannel {
+    sender: mpsc::Sender<(String, Result<ServerStatus, String>)>,
+    receiver: mpsc::Receiver<(String, Result<ServerStatus, String>)>,
+}
+
+impl Default for PingChannel {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel(32);
+        Self { sender, receiver }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Username,
+    Name,
+    Address,
+}
+
+/// The add/edit dialog. `editing` is the index being edited, `None` when adding.
+struct ServerForm {
+    editing: Option<usize>,
+    name: String,
+    address: String,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct MenuState {
+    selected: Option<usize>,
+    focus: Option<TextField>,
+    form: Option<ServerForm>,
+    /// Why the last session ended, shown until the next connect.
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Component)]
+pub(crate) struct MenuRoot;
+
+#[derive(Component, Clone, Copy)]
+pub(crate) enum MenuButton {
+    Select(usize),
+    Add,
+    Edit,
+    Delete,
+    Refresh,
+    Connect,
+    Save,
+    Cancel,
+    Focus(TextField),
+    /// Join the LAN world at this index of [`LanServers`].
+    JoinLan(usize),
+}
+
+pub(crate) fn open_menu(
+    saved: Res<SavedServers>,
+    mut menu_state: ResMut<MenuState>,
+    mut pings: ResMut<ServerPings>,
+    channel: Res<PingChannel>,
+) {
+    if menu_state.selected.is_none() && !saved.servers.is_empty() {
+        menu_state.selected = Some(0);
+    }
+    for server in &saved.servers {
+        start_ping(&server.address, &mut pings, &channel);
+    }
+}
+
+fn start_ping(address: &str, pings: &mut ServerPings, channel: &PingChannel) {
+    pings.results.insert(address.to_string(), PingResult::Pending);
+
+    let sender = channel.sender.clone();
+    let address = address.to_string();
+    AsyncComputeTaskPool::get().spawn(async move {
+        let result = query_status(&address).map_err(|e| e.to_string());
+        let _ = sender.send((address, result)).await;
+    }).detach();
+}
+
+pub(crate) fn receive_pings(
+    mut channel: ResMut<PingChannel>,
+    mut pings: ResMut<ServerPings>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    while let Ok((address, result)) = channel.receiver.try_recv() {
+        let entry = match result {
+            Ok(status) => {
+                let favicon = status.favicon.as_deref().and_then(|png| {
+                    Image::from_buffer(
+                        png,
+                        ImageType::Extension("png"),
+                        CompressedImageFormats::NONE,
+                        true,
+                        ImageSampler::nearest(),
+                        RenderAssetUsages::default(),
+                    )
+                    .map_err(|e| debug!("Bad favicon from {}: {}", address, e))
+                    .ok()
+                    .map(|image| match pings.favicons.get(&address) {
+                        Some(handle) => {
+                            images.insert(handle, image);
+                            handle.clone()
+                        }
+                        None => {
+                            let handle = images.add(image);
+                            pings.favicons.insert(address.clone(), handle.clone());
+                            handle
+                        }
+                    })
+                });
+                PingResult::Online { status, favicon }
+            }
+            Err(e) => PingResult::Offline(e),
+        };
+        pings.results.insert(address, entry);
+    }
+}
+
+pub(crate) fn handle_menu_buttons(
+    interaction_query: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut saved: ResMut<SavedServers>,
+    mut menu_state: ResMut<MenuState>,
+    mut pings: ResMut<ServerPings>,
+    mut images: ResMut<Assets<Image>>,
+    channel: Res<PingChannel>,
+    lan_servers: Res<LanServers>,
+    mut server_address: ResMut<ServerAddress>,
+    mut username: ResMut<Username>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match *button {
+            MenuButton::Select(index) => menu_state.selected = Some(index),
+            MenuButton::Focus(field) => menu_state.focus = Some(field),
+            MenuButton::Add => {
+                menu_state.form = Some(ServerForm {
+                    editing: None,
+                    name: "New server".to_string(),
+                    address: "127.0.0.1:25565".to_string(),
+                });
+                menu_state.focus = Some(TextField::Name);
+            }
+            MenuButton::Edit => {
+                if let Some(server) = menu_state.selected.and_then(|i| saved.servers.get(i)) {
+                    let form = ServerForm {
+                        editing: menu_state.selected,
+                        name: server.name.clone(),
+                        address: server.address.clone(),
+                    };
+                    menu_state.form = Some(form);
+                    menu_state.focus = Some(TextField::Name);
+                }
+            }
+            MenuButton::Delete => {
+                if let Some(index) = menu_state.selected.filter(|&i| i < saved.servers.len()) {
+                    let removed = saved.servers.remove(index);
+                    pings.remove(&removed.address, &mut images);
+                    menu_state.selected = None;
+                    saved.save();
+                }
+            }
+            MenuButton::Refresh => {
+                for server in &saved.servers {
+                    start_ping(&server.address, &mut pings, &channel);
+                }
+            }
+            MenuButton::Save => {
+                if let Some(form) = menu_state.form.take() {
+                    let server = SavedServer {
+                        name: form.name.trim().to_string(),
+                        address: form.address.trim().to_string(),
+                    };
+                    start_ping(&server.address, &mut pings, &channel);
+                    match form.editing.filter(|&i| i < saved.servers.len()) {
+                        Some(index) => saved.servers[index] = server,
+                        None => {
+                            saved.servers.push(server);
+                            menu_state.selected = Some(saved.servers.len() - 1);
+                        }
+                    }
+                    menu_state.focus = None;
+                    saved.save();
+                }
+            }
+            MenuButton::Cancel => {
+                menu_state.form = None;
+                menu_state.focus = None;
+            }
+            MenuButton::Connect => {
+                let Some(server) = menu_state.selected.and_then(|i| saved.servers.get(i)) else {
+                    continue;
+                };
+                if saved.username.is_empty() {
+                    continue;
+                }
+                info!("Connecting to {} ({}) as {}", server.name, server.address, saved.username);
+                server_address.0 = server.address.clone();
+                username.0 = saved.username.clone();
+                menu_state.error = None;
+                saved.save();
+                next_state.set(AppState::InGame);
+            }
+            MenuButton::JoinLan(index) => {
+                let Some(lan_server) = lan_servers.0.get(index) else {
+                    continue;
+                };
+                if saved.username.is_empty() {
+                    continue;
+                }
+                info!("Joining LAN world {:?} at {} as {}", lan_server.motd, lan_server.address, saved.username);
+                server_address.0 = lan_server.address.clone();
+                username.0 = saved.username.clone();
+                menu_state.error = None;
+                saved.save();
+                next_state.set(AppState::InGame);
+            }
+        }
+    }
+}
+
+/// Types into whichever field has focus.
+pub(crate) fn handle_text_input(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut saved: ResMut<SavedServers>,
+    mut menu_state: ResMut<MenuState>,
+) {
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        let Some(field) = menu_state.focus else {
+            continue;
+        };
+
+        let text = match field {
+            TextField::Username => &mut saved.username,
+            TextField::Name => match menu_state.form.as_mut() {
+                Some(form) => &mut form.name,
+                None => continue,
+            },
+            TextField::Address => match menu_state.form.as_mut() {
+                Some(form) => &mut form.address,
+                None => continue,
+            },
+        };
+
+        match &event.logical_key {
+            Key::Character(chars) => {
+                // Usernames are limited to 16 characters by the login packet.
+                if field != TextField::Username || text.len() + chars.len() <= 16 {
+                    text.extend(chars.chars().filter(|c| !c.is_control()));
+                }
+            }
+            Key::Space if field == TextField::Name => text.push(' '),
+            Key::Backspace => {
+                text.pop();
+            }
+            Key::Tab => {
+                menu_state.focus = match field {
+                    TextField::Name => Some(TextField::Address),
+                    TextField::Address => Some(TextField::Name),
+                    TextField::Username => Some(TextField::Username),
+                };
+            }
+            Key::Escape | Key::Enter => menu_state.focus = None,
+            _ => {}
+        }
+    }
+}
+
+/// The menu is small, so it's simply rebuilt whenever anything it shows changes.
+pub(crate) fn rebuild_menu(
+    mut commands: Commands,
+    root_query: Query<Entity, With<MenuRoot>>,
+    saved: Res<SavedServers>,
+    pings: Res<ServerPings>,
+    lan_servers: Res<LanServers>,
+    menu_state: Res<MenuState>,
+) {
+    if !(saved.is_changed() || pings.is_changed() || lan_servers.is_changed() || menu_state.is_changed()) {
+        return;
+    }
+    for root in &root_query {
+        commands.entity(root).despawn_recursive();
+    }
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(24.0)),
+                    row_gap: Val::Px(12.0),
+                    ..default()
+                },
+                background_color: BACKGROUND.into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            MenuRoot,
+        ))
+        .with_children(|root| {
+            root.spawn(label("Servers", 32.0, Color::WHITE));
+            if let Some(error) = &menu_state.error {
+                root.spawn(label(error, 20.0, ERROR_TEXT));
+            }
+
+            root.spawn(row()).with_children(|row| {
+                row.spawn(label("Username:", 20.0, Color::WHITE));
+                spawn_field(row, &saved.username, TextField::Username, menu_state.focus);
+            });
+
+            root.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.0),
+                    flex_grow: 1.0,
+                    overflow: Overflow::clip_y(),
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|list| {
+                for (index, server) in saved.servers.iter().enumerate() {
+                    spawn_server_row(list, index, server, pings.results.get(&server.address), menu_state.selected == Some(index));
+                }
+
+                if !lan_servers.0.is_empty() {
+                    list.spawn(label("LAN worlds", 24.0, Color::WHITE));
+                }
+                for (index, lan_server) in lan_servers.0.iter().enumerate() {
+                    list.spawn(row()).with_children(|row| {
+                        spawn_button(row, "Join", MenuButton::JoinLan(index));
+                        row.spawn(label(&lan_server.motd, 20.0, Color::WHITE));
+                        row.spawn(label(&lan_server.address, 16.0, DIM_TEXT));
+                    });
+                }
+            });
+
+            if let Some(form) = &menu_state.form {
+                root.spawn(row()).with_children(|row| {
+                    row.spawn(label("Name:", 20.0, Color::WHITE));
+                    spawn_field(row, &form.name, TextField::Name, menu_state.focus);
+                    row.spawn(label("Address:", 20.0, Color::WHITE));
+                    spawn_field(row, &form.address, TextField::Address, menu_state.focus);
+                    spawn_button(row, "Save", MenuButton::Save);
+                    spawn_button(row, "Cancel", MenuButton::Cancel);
+                });
+            }
+
+            root.spawn(row()).with_children(|row| {
+                spawn_button(row, "Add", MenuButton::Add);
+                spawn_button(row, "Edit", MenuButton::Edit);
+                spawn_button(row, "Delete", MenuButton::Delete);
+                spawn_button(row, "Refresh", MenuButton::Refresh);
+                spawn_button(row, "Connect", MenuButton::Connect);
+            });
+        });
+}
+
+pub(crate) fn despawn_menu(mut commands: Commands, root_query: Query<Entity, With<MenuRoot>>) {
+    for root in &root_query {
+        commands.entity(root).despawn_recursive();
+    }
+}
+
+fn spawn_server_row(
+    list: &mut ChildBuilder,
+    index: usize,
+    server: &SavedServer,
+    ping: Option<&PingResult>,
+    selected: bool,
+) {
+    let (motd, players, latency, favicon) = match ping {
+        Some(PingResult::Online { status, favicon }) => (
+            status.motd.clone(),
+            format!("{}/{}", status.players_online, status.players_max),
+            format!("{} ms", status.latency.as_millis()),
+            favicon.clone(),
+        ),
+        Some(PingResult::Offline(reason)) => (format!("Can't reach server: {}", reason), String::new(), "-".to_string(), None),
+        Some(PingResult::Pending) | None => ("Pinging...".to_string(), String::new(), "...".to_string(), None),
+    };
+
+    list.spawn((
+        ButtonBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(12.0),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            background_color: if selected { ROW_SELECTED } else { ROW }.into(),
+            ..default()
+        },
+        MenuButton::Select(index),
+    ))
+    .with_children(|row| {
+        let icon_style = Style {
+            width: Val::Px(48.0),
+            height: Val::Px(48.0),
+            ..default()
+        };
+        match favicon {
+            Some(image) => {
+                row.spawn(ImageBundle {
+                    style: icon_style,
+                    image: UiImage::new(image),
+                    ..default()
+                });
+            }
+            None => {
+                row.spawn(NodeBundle {
+                    style: icon_style,
+                    background_color: FIELD.into(),
+                    ..default()
+                });
+            }
+        }
+
+        row.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                flex_grow: 1.0,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|column| {
+            column.spawn(label(&format!("{}  ({})", server.name, server.address), 20.0, Color::WHITE));
+            column.spawn(label(&motd, 16.0, DIM_TEXT));
+        });
+
+        row.spawn(label(&players, 18.0, Color::WHITE));
+        row.spawn(label(&latency, 18.0, DIM_TEXT));
+    });
+}
+
+fn spawn_field(parent: &mut ChildBuilder, value: &str, field: TextField, focus: Option<TextField>) {
+    let border = if focus == Some(field) { FIELD_FOCUSED } else { BUTTON };
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    min_width: Val::Px(200.0),
+                    padding: UiRect::all(Val::Px(4.0)),
+                    border: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                border_color: border.into(),
+                background_color: FIELD.into(),
+                ..default()
+            },
+            MenuButton::Focus(field),
+        ))
+        .with_children(|field| {
+            field.spawn(label(value, 20.0, Color::WHITE));
+        });
+}
+
+fn spawn_button(parent: &mut ChildBuilder, text: &str, action: MenuButton) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(16.0), Val::Px(6.0)),
+                    ..default()
+                },
+                background_color: BUTTON.into(),
+                ..default()
+            },
+            action,
+        ))
+        .with_children(|button| {
+            button.spawn(label(text, 20.0, Color::WHITE));
+        });
+}
+
+fn row() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(8.0),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+fn label(text: &str, font_size: f32, color: Color) -> TextBundle {
+    TextBundle::from_section(
+        text,
+        TextStyle {
+            font_size,
+            color,
+            ..default()
+        },
+    )
+}
@@ -0,0 +1,514 @@
+use bevy::color::{Alpha, Color, ColorToComponents, LinearRgba};
+use bevy::math::{IVec3, Vec2};
+use bevy::prelude::Mesh;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use valence_protocol::block::BlockKind;
+use valence_protocol::BlockState;
+use crate::block_models::{block_boxes, covers_face, is_solid_cube};
+use crate::blocks::{
+    fluid_state, AtlasRect, BlockAppearance, BlockAppearances, BlockElement, FaceAppearance, Fluid, Tint, Transparency,
+};
+use crate::registry::{Biome, Registries};
+use crate::world::{ChunkStore, SECTION_SIZE};
+
+pub(crate) struct Face {
+    pub(crate) normal: IVec3,
+    /// Unit cube corners, counter-clockwise seen from outside.
+    pub(crate) corners: [[f32; 3]; 4],
+}
+
+pub(crate) const FACES: [Face; 6] = [
+    Face { normal: IVec3::X, corners: [[1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [1.0, 1.0, 1.0], [1.0, 0.0, 1.0]] },
+    Face { normal: IVec3::NEG_X, corners: [[0.0, 0.0, 1.0], [0.0, 1.0, 1.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.0]] },
+    Face { normal: IVec3::Y, corners: [[0.0, 1.0, 0.0], [0.0, 1.0, 1.0], [1.0, 1.0, 1.0], [1.0, 1.0, 0.0]] },
+    Face { normal: IVec3::NEG_Y, corners: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 0.0, 1.0]] },
+    Face { normal: IVec3::Z, corners: [[1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0], [0.0, 0.0, 1.0]] },
+    Face { normal: IVec3::NEG_Z, corners: [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0], [1.0, 0.0, 0.0]] },
+];
+
+/// Collects quads and turns them into a Bevy mesh.
+#[derive(Default)]
+pub(crate) struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    /// Adds a quad split along the 0-2 diagonal, or along 1-3 when `flip` is set.
+    pub(crate) fn push_quad(
+        &mut self,
+        corners: [[f32; 3]; 4],
+        normal: [f32; 3],
+        uvs: [[f32; 2]; 4],
+        colors: [[f32; 4]; 4],
+        flip: bool,
+    ) {
+        let start = self.positions.len() as u32;
+        self.positions.extend(corners);
+        self.normals.extend([normal; 4]);
+        self.uvs.extend(uvs);
+        self.colors.extend(colors);
+        if flip {
+            self.indices.extend([start + 1, start + 2, start + 3, start + 1, start + 3, start]);
+        } else {
+            self.indices.extend([start, start + 1, start + 2, start, start + 2, start + 3]);
+        }
+    }
+
+    pub(crate) fn build(self) -> Option<Mesh> {
+        if self.indices.is_empty() {
+            return None;
+        }
+        Some(
+            Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+                .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+                .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+                .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+                .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.colors)
+                .with_inserted_indices(Indices::U32(self.indices)),
+        )
+    }
+}
+
+/// The meshes of one section, split by the material they're drawn with.
+#[derive(Default)]
+pub(crate) struct SectionMeshes {
+    pub(crate) solid: Option<Mesh>,
+    pub(crate) cutout: Option<Mesh>,
+    /// Positioned relative to [`TRANSLUCENT_PIVOT`] so sections sort by their center.
+    pub(crate) translucent: Option<Mesh>,
+    /// Faces of light-emitting blocks, drawn unlit so they stay bright in the dark.
+    pub(crate) emissive: Option<Mesh>,
+    /// Flowing fluid surfaces, textured with the scrolling flow textures instead of the
+    /// atlas. Flowing water shares the translucent pivot.
+    pub(crate) flowing_water: Option<Mesh>,
+    pub(crate) flowing_lava: Option<Mesh>,
+}
+
+/// Where the translucent mesh's origin sits inside its section, on every axis.
+pub(crate) const TRANSLUCENT_PIVOT: f32 = 8.0;
+
+/// Builds the meshes of one section, in section-local coordinates. Blocks are drawn as
+/// the elements of their resource pack model, or the boxes of their shape without one;
+/// faces against opaque neighbours are skipped, looking across section and chunk borders
+/// through the store.
+pub(crate) fn build_section_meshes(
+    store: &ChunkStore,
+    section_pos: IVec3,
+    registries: &Registries,
+    appearances: &BlockAppearances,
+) -> SectionMeshes {
+    let Some(section) = store.section(section_pos) else {
+        return SectionMeshes::default();
+    };
+    if section.is_empty() {
+        return SectionMeshes::default();
+    }
+
+    let origin = IVec3::new(
+        section_pos.x * SECTION_SIZE,
+        store.min_y() + section_pos.y * SECTION_SIZE,
+        section_pos.z * SECTION_SIZE,
+    );
+    let mut solid = MeshBuilder::default();
+    let mut cutout = MeshBuilder::default();
+    let mut translucent = MeshBuilder::default();
+    let mut emissive = MeshBuilder::default();
+    let mut flowing_water = MeshBuilder::default();
+    let mut flowing_lava = MeshBuilder::default();
+    let light_at = |local: IVec3| store.light(origin + local);
+    let state_at = |local: IVec3| {
+        if local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(16)).all() {
+            Some(section.block(local.x as usize, local.y as usize, local.z as usize))
+        } else {
+            store.block(origin + local)
+        }
+    };
+
+    for y in 0..16 {
+        for z in 0..16 {
+            for x in 0..16 {
+                let state = section.block(x, y, z);
+                if state.is_air() {
+                    continue;
+                }
+
+                let local = IVec3::new(x as i32, y as i32, z as i32);
+                let biome = registries.biome(section.biome(x, y, z));
+
+                if let Some((fluid, _)) = fluid_state(state) {
+                    let fluid_appearance = appearances.appearance(fluid.block());
+                    let rect = appearances.fluid_still[fluid as usize];
+                    let has_flow = appearances.fluid_flow[fluid as usize].is_some();
+                    let (still, flow) = match fluid {
+                        Fluid::Water => (
+                            (&mut translucent, TRANSLUCENT_PIVOT),
+                            has_flow.then_some((&mut flowing_water, TRANSLUCENT_PIVOT)),
+                        ),
+                        Fluid::Lava => ((&mut emissive, 0.0), has_flow.then_some((&mut flowing_lava, 0.0))),
+                    };
+                    let mut color = face_color(&fluid_appearance, &FaceAppearance { texture: rect, tinted: true, rotation: 0 }, biome);
+                    // Fluid models have no textured faces, so the flat-color alpha would
+                    // stack on top of the texture's own.
+                    if rect.is_some() {
+                        color.set_alpha(1.0);
+                    }
+                    let surface = FluidSurface {
+                        still,
+                        flow,
+                        rect: rect.unwrap_or(appearances.white),
+                        color: color.into(),
+                        emissive: fluid_appearance.emissive,
+                    };
+                    push_fluid(surface, fluid, local, &state_at, &light_at);
+
+                    // Waterlogged blocks are drawn as themselves on top of their water.
+                    if matches!(state.to_kind(), BlockKind::Water | BlockKind::Lava | BlockKind::BubbleColumn) {
+                        continue;
+                    }
+                }
+
+                let appearance = appearances.appearance(state);
+                let (builder, pivot) = match appearance.transparency {
+                    _ if appearance.emissive => (&mut emissive, 0.0),
+                    Transparency::Opaque => (&mut solid, 0.0),
+                    Transparency::Cutout => (&mut cutout, 0.0),
+                    Transparency::Translucent => (&mut translucent, TRANSLUCENT_PIVOT),
+                };
+
+                // Without a model from the resource pack, the block's shape is drawn untextured.
+                let fallback: Vec<BlockElement>;
+                let elements = match appearances.elements(state) {
+                    Some(elements) => elements,
+                    None => {
+                        fallback = block_boxes(state)
+                            .into_iter()
+                            .map(|model_box| BlockElement { model_box, faces: appearance.faces.map(Some) })
+                            .collect();
+                        &fallback
+                    }
+                };
+
+                for BlockElement { model_box, faces } in elements {
+                    for (index, face) in FACES.iter().enumerate() {
+                        let Some(face_appearance) = faces[index] else {
+                            continue;
+                        };
+                        // Only faces on the block's sides can be hidden by a neighbour.
+                        let on_side = model_box.touches_side(index);
+                        if on_side
+                            && state_at(local + face.normal)
+                                .is_some_and(|neighbour| hides_face(state, &appearance, neighbour, index))
+                        {
+                            continue;
+                        }
+
+                        let color = LinearRgba::from(face_color(&appearance, &face_appearance, biome));
+                        let box_corners = face.corners.map(|corner| model_box.corner(corner));
+                        let brightness = face.corners.map(|corner| {
+                            // Light-emitting blocks are drawn unlit at full brightness.
+                            if appearance.emissive {
+                                return 1.0;
+                            }
+                            // Faces inside the block see its own light, without occlusion.
+                            if !on_side {
+                                return light_at(local)
+                                    .map_or(1.0, |(block, sky)| light_brightness(block.max(sky) as f32));
+                            }
+                            let samples = corner_neighbours(face.normal, corner)
+                                .map(|offset| (state_at(local + offset), light_at(local + offset)));
+                            smooth_light(&samples) * AO_BRIGHTNESS[ambient_occlusion(&samples)]
+                        });
+                        let colors = brightness.map(|brightness| (color * brightness).with_alpha(color.alpha).to_f32_array());
+                        // Split along the brighter diagonal so the shading doesn't depend on
+                        // which way the quad happens to be triangulated.
+                        let flip = brightness[0] + brightness[2] < brightness[1] + brightness[3];
+                        let rect = face_appearance.texture.unwrap_or(appearances.white);
+                        let corners = box_corners
+                            .map(|[cx, cy, cz]| [cx + x as f32 - pivot, cy + y as f32 - pivot, cz + z as f32 - pivot]);
+                        let uvs = face.corners.map(|corner| rotated_face_uv(face.normal, corner, rect, face_appearance.rotation));
+                        builder.push_quad(corners, face.normal.as_vec3().to_array(), uvs, colors, flip);
+                    }
+                }
+            }
+        }
+    }
+
+    SectionMeshes {
+        solid: solid.build(),
+        cutout: cutout.build(),
+        translucent: translucent.build(),
+        emissive: emissive.build(),
+        flowing_water: flowing_water.build(),
+        flowing_lava: flowing_lava.build(),
+    }
+}
+
+/// Where a fluid block's faces go and how they're colored.
+struct FluidSurface<'a> {
+    /// Builder for still surfaces, and the pivot its vertices are relative to.
+    still: (&'a mut MeshBuilder, f32),
+    /// Builder for flowing surfaces and sides, if the fluid has a flow texture.
+    flow: Option<(&'a mut MeshBuilder, f32)>,
+    /// Still texture in the atlas.
+    rect: AtlasRect,
+    color: LinearRgba,
+    emissive: bool,
+}
+
+/// Flow textures cover two blocks, like vanilla's.
+const FLOW_UV_SCALE: f32 = 0.5;
+
+/// Meshes the fluid in the block at `local`. The surface sits at a height given by the
+/// fluid's level, sloping at each corner towards the surrounding fluid, and is left out
+/// when more of the same fluid is above. Sides and bottom are skipped against the same
+/// fluid or opaque blocks.
+fn push_fluid(
+    surface: FluidSurface,
+    fluid: Fluid,
+    local: IVec3,
+    state_at: &impl Fn(IVec3) -> Option<BlockState>,
+    light_at: &impl Fn(IVec3) -> Option<(u8, u8)>,
+) {
+    let FluidSurface { still, mut flow, rect, color, emissive } = surface;
+    let same_fluid = |pos: IVec3| state_at(pos).and_then(fluid_state).is_some_and(|(other, _)| other == fluid);
+    let covered = same_fluid(local + IVec3::Y);
+
+    // Surface heights in the order of the top face's corners.
+    let top = &FACES[2];
+    let heights = top.corners.map(|[cx, _, cz]| {
+        if covered {
+            1.0
+        } else {
+            fluid_corner_height(fluid, local + IVec3::new(cx as i32, 0, cz as i32), state_at)
+        }
+    });
+    let height_at = |x: f32, z: f32| {
+        let index = top.corners.iter().position(|&[cx, _, cz]| cx == x && cz == z).unwrap_or(0);
+        heights[index]
+    };
+
+    let (still_builder, still_pivot) = still;
+    for (index, face) in FACES.iter().enumerate() {
+        let neighbour = local + face.normal;
+        let corners = match index {
+            2 if covered => continue,
+            2 => top.corners.map(|[cx, _, cz]| [cx, height_at(cx, cz), cz]),
+            _ if same_fluid(neighbour) || state_at(neighbour).is_some_and(|state| covers_face(state, index ^ 1)) => continue,
+            3 => face.corners,
+            _ => face.corners.map(|[cx, cy, cz]| [cx, if cy > 0.0 { height_at(cx, cz) } else { 0.0 }, cz]),
+        };
+
+        let brightness = if emissive {
+            1.0
+        } else {
+            [light_at(local), light_at(neighbour)]
+                .into_iter()
+                .flatten()
+                .map(|(block, sky)| block.max(sky))
+                .max()
+                .map_or(1.0, |level| light_brightness(level as f32))
+        };
+        let colors = [(color * brightness).with_alpha(color.alpha).to_f32_array(); 4];
+
+        // Tops slope downhill from the higher corners; a level surface is still.
+        let downhill = if index == 2 {
+            let slope = Vec2::new(heights[2] + heights[3] - heights[0] - heights[1], heights[1] + heights[2] - heights[0] - heights[3]);
+            -slope.normalize_or_zero()
+        } else {
+            Vec2::ZERO
+        };
+        let flow_uvs = match index {
+            2 if downhill != Vec2::ZERO => Some(corners.map(|[cx, _, cz]| {
+                let offset = Vec2::new(cx - 0.5, cz - 0.5);
+                [offset.dot(downhill.perp()) * FLOW_UV_SCALE, offset.dot(downhill) * FLOW_UV_SCALE]
+            })),
+            0 | 1 | 4 | 5 => Some(corners.map(|corner @ [_, cy, _]| {
+                let [u, _] = face_uv(face.normal, corner, [0.0, 0.0, 1.0, 1.0]);
+                [u * FLOW_UV_SCALE, (1.0 - cy) * FLOW_UV_SCALE]
+            })),
+            _ => None,
+        };
+
+        let (builder, pivot, uvs) = match (flow.as_mut(), flow_uvs) {
+            (Some((builder, pivot)), Some(uvs)) => (&mut **builder, *pivot, uvs),
+            _ => (&mut *still_builder, still_pivot, corners.map(|corner| face_uv(face.normal, corner, rect))),
+        };
+        let corners = corners.map(|[cx, cy, cz]| {
+            [cx + local.x as f32 - pivot, cy + local.y as f32 - pivot, cz + local.z as f32 - pivot]
+        });
+        builder.push_quad(corners, face.normal.as_vec3().to_array(), uvs, colors, false);
+    }
+}
+
+/// Surface height of a fluid block: a source or falling fluid nearly fills the block and
+/// each level it spreads drops it by a ninth.
+fn fluid_height(level: u8) -> f32 {
+    let level = if level >= 8 { 0 } else { level };
+    (8 - level) as f32 / 9.0
+}
+
+/// Height of the fluid surface at the corner whose +X/+Z block is `corner`, averaged over
+/// the four blocks around it like vanilla: sources weigh more, open blocks pull the
+/// surface down and opaque ones are ignored. Fluid above any of them fills the corner.
+fn fluid_corner_height(fluid: Fluid, corner: IVec3, state_at: &impl Fn(IVec3) -> Option<BlockState>) -> f32 {
+    let (mut total, mut weight) = (0.0, 0.0);
+    for offset in [IVec3::ZERO, IVec3::NEG_X, IVec3::NEG_Z, IVec3::new(-1, 0, -1)] {
+        let column = corner + offset;
+        if state_at(column + IVec3::Y).and_then(fluid_state).is_some_and(|(other, _)| other == fluid) {
+            return 1.0;
+        }
+        let Some(state) = state_at(column) else {
+            continue;
+        };
+        match fluid_state(state) {
+            Some((other, level)) if other == fluid => {
+                let height = fluid_height(level);
+                let samples = if height >= 0.8 { 10.0 } else { 1.0 };
+                total += height * samples;
+                weight += samples;
+            }
+            _ if is_solid_cube(state) => {}
+            _ => weight += 1.0,
+        }
+    }
+    if weight == 0.0 {
+        return 0.0;
+    }
+    total / weight
+}
+
+/// Darkest a fully unlit face gets, so caves aren't pure black.
+const MIN_BRIGHTNESS: f32 = 0.04;
+/// Brightness by ambient occlusion level, from a corner tucked into a crease to an open one.
+const AO_BRIGHTNESS: [f32; 4] = [0.45, 0.6, 0.8, 1.0];
+
+/// Offsets of the blocks around a face corner on the outside of the face: straight in
+/// front, the two along the face's edges, and the diagonal.
+fn corner_neighbours(normal: IVec3, [x, y, z]: [f32; 3]) -> [IVec3; 4] {
+    // Per axis, -1 or +1 towards the corner.
+    let towards = IVec3::new(x as i32, y as i32, z as i32) * 2 - IVec3::ONE;
+    let mut sides = [IVec3::ZERO; 2];
+    let mut side = 0;
+    for axis in 0..3 {
+        if normal[axis] == 0 {
+            sides[side][axis] = towards[axis];
+            side += 1;
+        }
+    }
+    [normal, normal + sides[0], normal + sides[1], normal + sides[0] + sides[1]]
+}
+
+/// Averages block and sky light over the transparent blocks around a corner, in the
+/// order of [`corner_neighbours`], and maps the result to a brightness.
+fn smooth_light(samples: &[(Option<BlockState>, Option<(u8, u8)>); 4]) -> f32 {
+    let (mut block, mut sky, mut count) = (0.0, 0.0, 0.0);
+    for (index, (state, light)) in samples.iter().enumerate() {
+        // The block in front always counts; it's what the face is looking at.
+        if index > 0 && state.is_some_and(is_solid_cube) {
+            continue;
+        }
+        if let Some((block_light, sky_light)) = light {
+            block += *block_light as f32;
+            sky += *sky_light as f32;
+            count += 1.0;
+        }
+    }
+    if count == 0.0 {
+        return 1.0;
+    }
+    light_brightness(f32::max(block, sky) / count)
+}
+
+/// Occlusion level 0 (darkest) to 3 of a corner from the opaque blocks beside and
+/// diagonal to it, in the order of [`corner_neighbours`]. Two solid sides close the
+/// corner off regardless of the diagonal.
+fn ambient_occlusion(samples: &[(Option<BlockState>, Option<(u8, u8)>); 4]) -> usize {
+    let solid = |index: usize| samples[index].0.is_some_and(is_solid_cube);
+    let (side1, side2, corner) = (solid(1), solid(2), solid(3));
+    if side1 && side2 {
+        0
+    } else {
+        3 - (side1 as usize + side2 as usize + corner as usize)
+    }
+}
+
+/// Vanilla's light curve without the brightness slider: dim levels fall off quickly.
+fn light_brightness(level: f32) -> f32 {
+    let fraction = (level / 15.0).clamp(0.0, 1.0);
+    MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * fraction / (4.0 - 3.0 * fraction)
+}
+
+/// Opaque neighbours hide faces on the sides they fill; touching blocks of the same
+/// translucent kind, like stained glass next to stained glass, hide the face between them.
+fn hides_face(state: BlockState, appearance: &BlockAppearance, neighbour: BlockState, face: usize) -> bool {
+    // The neighbour's side facing back at us.
+    covers_face(neighbour, face ^ 1)
+        || (appearance.transparency == Transparency::Translucent && neighbour.to_kind() == state.to_kind())
+}
+
+fn face_color(appearance: &BlockAppearance, face: &FaceAppearance, biome: Option<&Biome>) -> Color {
+    let tint = match (face.texture, appearance.tint) {
+        (Some(_), Some(tint)) if face.tinted => tint,
+        (Some(_), _) => return Color::WHITE.with_alpha(appearance.base_color.alpha()),
+        (None, Some(tint)) => tint,
+        (None, None) => return appearance.base_color,
+    };
+    let color = match (tint, biome) {
+        (Tint::Grass, Some(biome)) => biome_grass_color(biome),
+        (Tint::Foliage, Some(biome)) => biome_foliage_color(biome),
+        (Tint::Water, Some(biome)) => rgb(biome.water_color),
+        (Tint::Fixed(color), _) => rgb(color),
+        (_, None) => appearance.base_color,
+    };
+    color.with_alpha(appearance.base_color.alpha())
+}
+
+/// Texture coordinates of a face corner, oriented so textures are upright on the sides
+/// when seen from outside.
+pub(crate) fn face_uv(normal: IVec3, [x, y, z]: [f32; 3], [u0, v0, u1, v1]: AtlasRect) -> [f32; 2] {
+    let (u, v) = match normal.to_array() {
+        [1, 0, 0] => (1.0 - z, 1.0 - y),
+        [-1, 0, 0] => (z, 1.0 - y),
+        [0, 0, 1] => (x, 1.0 - y),
+        [0, 0, -1] => (1.0 - x, 1.0 - y),
+        [0, 1, 0] => (x, z),
+        _ => (x, 1.0 - z),
+    };
+    [u0 + (u1 - u0) * u, v0 + (v1 - v0) * v]
+}
+
+/// Like [`face_uv`] with `rect` stretched over the whole face, and the texture turned
+/// `rotation` quarter turns clockwise.
+fn rotated_face_uv(normal: IVec3, corner: [f32; 3], [u0, v0, u1, v1]: AtlasRect, rotation: u8) -> [f32; 2] {
+    let [mut u, mut v] = face_uv(normal, corner, [0.0, 0.0, 1.0, 1.0]);
+    for _ in 0..rotation % 4 {
+        (u, v) = (v, 1.0 - u);
+    }
+    [u0 + (u1 - u0) * u, v0 + (v1 - v0) * v]
+}
+
+pub(crate) fn rgb(color: u32) -> Color {
+    Color::srgb_u8((color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
+
+/// Biome grass color, approximating the vanilla color map from temperature and downfall
+/// when the biome doesn't override it.
+pub(crate) fn biome_grass_color(biome: &Biome) -> Color {
+    biome.grass_color.map_or_else(|| climate_color(biome, [0xbf, 0xb7, 0x55], [0x47, 0xcd, 0x33]), rgb)
+}
+
+pub(crate) fn biome_foliage_color(biome: &Biome) -> Color {
+    biome.foliage_color.map_or_else(|| climate_color(biome, [0xae, 0xa4, 0x2a], [0x1a, 0xbf, 0x00]), rgb)
+}
+
+fn climate_color(biome: &Biome, dry: [u8; 3], lush: [u8; 3]) -> Color {
+    let temperature = biome.temperature.clamp(0.0, 1.0);
+    let humidity = biome.downfall.clamp(0.0, 1.0) * temperature;
+    let mix = |i: usize| (dry[i] as f32 + (lush[i] as f32 - dry[i] as f32) * humidity) / 255.0;
+    Color::srgb(mix(0), mix(1), mix(2))
+}
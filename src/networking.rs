@@ -1,167 +1,560 @@
-use std::borrow::Cow;
-use std::io::Write;
+use bevy::log::{debug, info, trace, warn};
+use bevy::math::{IVec2, IVec3, Vec3};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
 use std::net::TcpStream;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use valence_protocol::decode::PacketFrame;
-use valence_protocol::{PacketDecoder, PacketEncoder};
+use valence_protocol::{Decode, Encode, Packet, PacketDecoder, PacketEncoder, PacketState};
 use valence_protocol::block::{PropName, PropValue};
-use valence_protocol::packets::login::{LoginCompressionS2c, LoginSuccessS2c};
-use valence_protocol::packets::play::{AdvancementUpdateS2c, BlockUpdateS2c, ChatMessageC2s, ChatMessageS2c, ChunkDataS2c, CommandTreeS2c, DisconnectS2c, EntityAttributesS2c, EntitySetHeadYawS2c, EntityStatusS2c, GameJoinS2c, GameMessageS2c, HealthUpdateS2c, KeepAliveC2s, KeepAliveS2c, PlayerAbilitiesS2c, PlayerListS2c, PlayerPositionLookS2c, PlayerSpawnPositionS2c, RotateS2c, ScreenHandlerSlotUpdateS2c, SynchronizeTagsS2c, UpdateSelectedSlotS2c};
-use crate::events::{ApplicationEvent, ChunkBlockData};
+use valence_protocol::uuid::Uuid;
+use valence_protocol::{anyhow, BlockPos, BlockState, Bounded, Direction, FixedArray, GameMode, Hand, Ident, RawBytes, VarInt};
+use valence_protocol::packets::login::{LoginCompressionS2c, LoginDisconnectS2c, LoginHelloS2c, LoginKeyC2s, LoginSuccessS2c};
+use valence_protocol::packets::play::client_settings_c2s::{ChatMode, DisplayedSkinParts, MainArm};
+use valence_protocol::packets::play::game_state_change_s2c::GameEventKind;
+use valence_protocol::packets::play::{AdvancementUpdateS2c, BlockBreakingProgressS2c, BlockUpdateS2c, ChatMessageC2s, ChatMessageS2c, ChunkDataS2c, ClientSettingsC2s, CustomPayloadC2s, CustomPayloadS2c, ChunkDeltaUpdateS2c, CommandTreeS2c, DisconnectS2c, LightUpdateS2c, EntitiesDestroyS2c, EntityAttributesS2c, EntitySpawnS2c, EntitySetHeadYawS2c, EntityStatusS2c, GameJoinS2c, GameStateChangeS2c, GameMessageS2c, HandSwingC2s, HealthUpdateS2c, KeepAliveC2s, KeepAliveS2c, PlayerAbilitiesS2c, PlayerActionC2s, PlayerInteractBlockC2s, PlayerListS2c, PlayerPositionLookS2c, PlayerRespawnS2c, PlayerSpawnPositionS2c, PlayerSpawnS2c, ResourcePackSendS2c, RotateS2c, ScreenHandlerSlotUpdateS2c, SynchronizeTagsS2c, TeleportConfirmC2s, UnloadChunkS2c, UpdateSelectedSlotS2c, WorldTimeUpdateS2c};
+use crate::auth::{exchange_keys, AuthConfig};
 use crate::connection::ConnectionStatus;
-use std::io::Read;
-use valence_protocol::Packet;
-use crate::events::ApplicationEvent::ChunkData;
+use crate::error::NetworkError;
+use crate::events::{ApplicationEvent, ChunkBlockData, ClientCommand};
+use crate::entities::{TrackedEntity, PLAYER_ENTITY_KIND};
+use crate::registry::{DimensionType, Registries};
+use crate::options::{ChatVisibility, MainHand};
+use crate::server_pack::ResourcePackOffer;
+use crate::weather::WeatherChange;
+use crate::world::{parse_chunk_sections, parse_light, DimensionInfo};
 
-pub(crate) async fn handle_server_messages_inner(connection_status: &mut ConnectionStatus, sender: mpsc::Sender<ApplicationEvent>) {
+pub(crate) async fn handle_server_messages_inner(
+    connection_status: &mut ConnectionStatus,
+    sender: mpsc::Sender<ApplicationEvent>,
+    auth: AuthConfig,
+    commands: mpsc::UnboundedReceiver<ClientCommand>,
+) -> Result<(), NetworkError> {
     if !connection_status.connected {
-        return;
+        return Ok(());
     }
 
-    let mut dec = connection_status.decoder.take().unwrap();
-    let mut enc = connection_status.encoder.take().unwrap();
-    let mut stream = connection_status.stream.take().unwrap();
+    let (Some(mut dec), Some(mut enc), Some(mut stream)) = (
+        connection_status.decoder.take(),
+        connection_status.encoder.take(),
+        connection_status.stream.take(),
+    ) else {
+        connection_status.connected = false;
+        return Err(NetworkError::Closed);
+    };
+
+    let result = read_packets(&mut dec, &mut enc, &mut stream, sender, auth, commands).await;
+    connection_status.message = "Connection closed.".to_string();
+    connection_status.connected = false;
+
+    connection_status.decoder = Some(dec);
+    connection_status.encoder = Some(enc);
+    connection_status.stream = Some(stream);
+    result
+}
 
-    let mut buffer = Vec::with_capacity(4096);
-    buffer.resize(4096, 0);
+/// The vanilla client gives up on a server that hasn't sent a keep-alive for this long.
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often a blocked read wakes up to send queued commands and check [`KEEP_ALIVE_TIMEOUT`].
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Largest custom payload the server accepts; anything bigger fails to encode.
+const MAX_PLUGIN_MESSAGE_SIZE: usize = 32767;
+
+/// Per-connection bookkeeping for the packet loop.
+struct Session {
+    state: PacketState,
+    /// Our own UUID, known after `LoginSuccessS2c`.
+    player_uuid: Uuid,
+    started: Instant,
+    last_keep_alive: Option<Instant>,
+    /// Needed if the server turns out to be in online mode.
+    auth: AuthConfig,
+    /// The current dimension; chunk data is laid out according to it.
+    dimension: DimensionInfo,
+    /// Kept from `GameJoinS2c` since respawns only name the dimension type.
+    dimension_types: HashMap<String, DimensionType>,
+    /// Numbers our block changes so the server can acknowledge them.
+    sequence: i32,
+}
+
+impl Session {
+    fn new(auth: AuthConfig) -> Self {
+        Self {
+            state: PacketState::Login,
+            auth,
+            dimension: DimensionInfo::default(),
+            dimension_types: HashMap::new(),
+            player_uuid: Uuid::nil(),
+            started: Instant::now(),
+            last_keep_alive: None,
+            sequence: 0,
+        }
+    }
+
+    /// Time since the last sign of life, counting from the start of the session
+    /// until the first keep-alive arrives.
+    fn silence(&self) -> Duration {
+        self.last_keep_alive.unwrap_or(self.started).elapsed()
+    }
+
+    fn next_sequence(&mut self) -> VarInt {
+        self.sequence += 1;
+        VarInt(self.sequence)
+    }
+}
+
+async fn read_packets(
+    dec: &mut PacketDecoder,
+    enc: &mut PacketEncoder,
+    stream: &mut TcpStream,
+    sender: mpsc::Sender<ApplicationEvent>,
+    auth: AuthConfig,
+    mut commands: mpsc::UnboundedReceiver<ClientCommand>,
+) -> Result<(), NetworkError> {
+    let mut session = Session::new(auth);
+    let mut buffer = vec![0; 4096];
+
+    stream.set_read_timeout(Some(READ_POLL_INTERVAL))?;
 
     loop {
-        match stream.read(&mut buffer) {
-            Ok(size) => {
-                if size == 0 {
-                    println!("Server disconnected.");
-                    connection_status.message = "Connection closed.".to_string();
-                    connection_status.connected = false;
-                    break;
-                }
-                dec.queue_bytes((&buffer[..size]).into());
-
-                while let Ok(Some(frame)) = dec.try_next_packet() {
-                    if let Err(e) = process_packet(frame, &mut dec, &mut enc, &mut stream, sender.clone()).await {
-                        println!("Error processing packet or disconnection: {:?}", e);
-                        connection_status.message = "Connection closed.".to_string();
-                        connection_status.connected = false;
-                        break;
-                    }
-                }
+        // Other packets may keep flowing while keep-alives don't, so check on every pass.
+        if session.silence() > KEEP_ALIVE_TIMEOUT {
+            return Err(NetworkError::TimedOut(session.silence()));
+        }
+
+        if session.state == PacketState::Play {
+            while let Ok(command) = commands.try_recv() {
+                send_command(enc, stream, &mut session, command)?;
+            }
+        }
+
+        let size = match stream.read(&mut buffer) {
+            Ok(0) => {
+                info!("Server disconnected.");
+                return Err(NetworkError::Closed);
             }
-            Err(e) => {
-                println!("Error reading from stream: {:?}", e);
-                connection_status.message = "Connection closed.".to_string();
-                connection_status.connected = false;
-                break;
+            Ok(size) => size,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        dec.queue_bytes((&buffer[..size]).into());
+
+        while let Some(frame) = dec.try_next_packet().map_err(|source| NetworkError::Decode { packet: "packet frame", source })? {
+            match session.state {
+                PacketState::Login => process_login_packet(frame, &mut session, dec, enc, stream)?,
+                PacketState::Play => process_packet(frame, &mut session, enc, stream, sender.clone()).await?,
+                state => return Err(NetworkError::UnexpectedPacket { state, id: frame.id }),
             }
         }
     }
+}
 
-    connection_status.decoder = Some(dec);
-    connection_status.encoder = Some(enc);
-    connection_status.stream = Some(stream);
+fn send_command(enc: &mut PacketEncoder, stream: &mut TcpStream, session: &mut Session, command: ClientCommand) -> Result<(), NetworkError> {
+    debug!("Sending {:?}", command);
+    match command {
+        ClientCommand::ResourcePackStatus(status) => send_packet(enc, stream, &status),
+        ClientCommand::ClientSettings(options) => {
+            let skin = options.skin_parts;
+            send_packet(enc, stream, &ClientSettingsC2s {
+                locale: &options.locale,
+                view_distance: options.render_distance,
+                chat_mode: match options.chat_visibility {
+                    ChatVisibility::Full => ChatMode::Enabled,
+                    ChatVisibility::CommandsOnly => ChatMode::CommandsOnly,
+                    ChatVisibility::Hidden => ChatMode::Hidden,
+                },
+                chat_colors: options.chat_colors,
+                displayed_skin_parts: DisplayedSkinParts::new()
+                    .with_cape(skin.cape)
+                    .with_jacket(skin.jacket)
+                    .with_left_sleeve(skin.left_sleeve)
+                    .with_right_sleeve(skin.right_sleeve)
+                    .with_left_pants_leg(skin.left_pants_leg)
+                    .with_right_pants_leg(skin.right_pants_leg)
+                    .with_hat(skin.hat),
+                main_arm: match options.main_hand {
+                    MainHand::Left => MainArm::Left,
+                    MainHand::Right => MainArm::Right,
+                },
+                enable_text_filtering: options.text_filtering,
+                allow_server_listings: options.allow_server_listings,
+            })
+        }
+        ClientCommand::PluginMessage { channel, data } => {
+            let channel = match Ident::new(channel.as_str()) {
+                Ok(channel) => channel,
+                Err(e) => {
+                    warn!("Not sending plugin message on invalid channel {:?}: {}", channel, e);
+                    return Ok(());
+                }
+            };
+            if data.len() > MAX_PLUGIN_MESSAGE_SIZE {
+                warn!("Not sending {} byte plugin message on {}, the limit is {}", data.len(), channel, MAX_PLUGIN_MESSAGE_SIZE);
+                return Ok(());
+            }
+            send_packet(enc, stream, &CustomPayloadC2s { channel, data: Bounded(RawBytes(&data)) })
+        }
+        ClientCommand::Dig { action, block, face } => send_packet(enc, stream, &PlayerActionC2s {
+            action,
+            position: BlockPos::new(block.x, block.y, block.z),
+            direction: face_direction(face),
+            sequence: session.next_sequence(),
+        }),
+        ClientCommand::SwingArm => send_packet(enc, stream, &HandSwingC2s { hand: Hand::Main }),
+        ClientCommand::UseBlock { hand, block, face, cursor } => send_packet(enc, stream, &PlayerInteractBlockC2s {
+            hand,
+            position: BlockPos::new(block.x, block.y, block.z),
+            face: face_direction(face),
+            cursor_pos: valence_protocol::math::Vec3::new(cursor.x, cursor.y, cursor.z),
+            // Targeting never hits the block the camera is in.
+            head_inside_block: false,
+            sequence: session.next_sequence(),
+        }),
+    }
 }
 
-async fn process_packet(
+/// The protocol's name for a face, given in the mesher's order.
+fn face_direction(face: usize) -> Direction {
+    match face {
+        0 => Direction::East,
+        1 => Direction::West,
+        2 => Direction::Up,
+        3 => Direction::Down,
+        4 => Direction::South,
+        _ => Direction::North,
+    }
+}
+
+fn light_arrays(arrays: &[FixedArray<u8, 2048>]) -> Vec<[u8; 2048]> {
+    arrays.iter().map(|array| array.0).collect()
+}
+
+/// Finds a dimension type named by `P`, failing the connection if the codec lacks it.
+fn lookup_dimension_type<'a, P: Packet>(
+    dimension_types: &'a HashMap<String, DimensionType>,
+    name: &str,
+) -> Result<&'a DimensionType, NetworkError> {
+    dimension_types.get(name).ok_or_else(|| NetworkError::Decode {
+        packet: P::NAME,
+        source: anyhow::anyhow!("unknown dimension type {}", name),
+    })
+}
+
+/// Decodes `frame` as `P`, naming the packet in the error if it doesn't fit.
+pub(crate) fn decode_packet<'a, P>(frame: &'a PacketFrame) -> Result<P, NetworkError>
+where
+    P: Packet + Decode<'a>,
+{
+    frame.decode().map_err(|source| NetworkError::Decode { packet: P::NAME, source })
+}
+
+/// Encodes `packet` and writes it out immediately.
+pub(crate) fn send_packet<P>(enc: &mut PacketEncoder, stream: &mut TcpStream, packet: &P) -> Result<(), NetworkError>
+where
+    P: Packet + Encode,
+{
+    enc.append_packet(packet)
+        .map_err(|source| NetworkError::Encode { packet: P::NAME, source })?;
+    let data = enc.take();
+    trace!("Sending {}: {:?}", P::NAME, data);
+    stream.write_all(&data)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn process_login_packet(
     frame: PacketFrame,
+    session: &mut Session,
     dec: &mut PacketDecoder,
     enc: &mut PacketEncoder,
-    // socket: &mut TcpSocket<'_>,
     stream: &mut TcpStream,
-    sender: mpsc::Sender<ApplicationEvent>,
-) -> Result<(), ()> {
+) -> Result<(), NetworkError> {
     match frame.id {
+        LoginHelloS2c::ID => {
+            let packet: LoginHelloS2c = decode_packet(&frame)?;
+            info!("Server is in online mode, enabling encryption");
+
+            let keys = exchange_keys(&session.auth, packet.server_id, packet.public_key, packet.verify_token)?;
+            send_packet(enc, stream, &LoginKeyC2s {
+                shared_secret: &keys.encrypted_secret,
+                verify_token: &keys.encrypted_verify_token,
+            })?;
+
+            // Everything after LoginKeyC2s is AES/CFB8 encrypted in both directions.
+            enc.enable_encryption(&keys.shared_secret);
+            dec.enable_encryption(&keys.shared_secret);
+        }
         LoginCompressionS2c::ID => {
-            println!("LoginCompressionS2c");
-            // let packet: LoginCompressionS2c = frame.decode().expect("Failed to decode LoginCompressionS2c");
-            let threshold = 256;
-            // let threshold = packet.threshold.0;
-            println!("Compression threshold received: {}", threshold);
+            let packet: LoginCompressionS2c = decode_packet(&frame)?;
+            let threshold = packet.threshold.0;
+            info!("Compression threshold received: {}", threshold);
 
             // Set compression threshold for decoder and encoder
             dec.set_compression(valence_protocol::CompressionThreshold(threshold));
             enc.set_compression(valence_protocol::CompressionThreshold(threshold));
         }
-
         LoginSuccessS2c::ID => {
-            // heap_stats();
-            // sender.try_send(HardwareEvent::ToggleLed).unwrap();
-            let packet: LoginSuccessS2c =
-                frame.decode().expect("Failed to decode LoginSuccessS2c");
-            println!(
+            let packet: LoginSuccessS2c = decode_packet(&frame)?;
+            info!(
                 "Login successful! Username: {}, UUID: {}",
                 packet.username, packet.uuid
             );
+            session.player_uuid = packet.uuid;
+            session.state = PacketState::Play;
+        }
+        LoginDisconnectS2c::ID => {
+            let packet: LoginDisconnectS2c = decode_packet(&frame)?;
+            warn!("Login rejected by server: {}", packet.reason);
+            return Err(NetworkError::Kicked(packet.reason.to_string()));
         }
+        _ => return Err(NetworkError::UnexpectedPacket { state: session.state, id: frame.id }),
+    }
+    Ok(())
+}
+
+async fn process_packet(
+    frame: PacketFrame,
+    session: &mut Session,
+    enc: &mut PacketEncoder,
+    stream: &mut TcpStream,
+    sender: mpsc::Sender<ApplicationEvent>,
+) -> Result<(), NetworkError> {
+    match frame.id {
         GameJoinS2c::ID => {
-            println!("GameJoinS2c");
-            let packet: GameJoinS2c = frame.decode().expect("Failed to decode GameJoinS2c");
+            let packet: GameJoinS2c = decode_packet(&frame)?;
+            let registries = Registries::from_codec(&packet.registry_codec);
+            let dimension_type = packet.dimension_type_name.to_string();
+            let dimension = lookup_dimension_type::<GameJoinS2c>(&registries.dimension_types, &dimension_type)?;
 
+            let info = DimensionInfo {
+                dimension_name: packet.dimension_name.to_string(),
+                dimension_type,
+                min_y: dimension.min_y,
+                height: dimension.height,
+                has_skylight: dimension.has_skylight,
+                hashed_seed: packet.hashed_seed,
+                view_distance: packet.view_distance.0,
+                simulation_distance: packet.simulation_distance.0,
+                game_mode: packet.game_mode,
+            };
+            info!(
+                "Joined {} (min_y={}, height={}) in {:?}, {} biomes, {} damage types",
+                info.dimension_name, info.min_y, info.height, info.game_mode,
+                registries.biomes.len(), registries.damage_types.len()
+            );
+
+            session.dimension = info.clone();
+            session.dimension_types = registries.dimension_types.clone();
+            sender.send(ApplicationEvent::GameJoin { dimension: info, registries }).await?;
+        }
+        PlayerRespawnS2c::ID => {
+            let packet: PlayerRespawnS2c = decode_packet(&frame)?;
+            let dimension_type = packet.dimension_type_name.to_string();
+            let dimension = lookup_dimension_type::<PlayerRespawnS2c>(&session.dimension_types, &dimension_type)?;
+
+            // View and simulation distance only come with the join, so carry them over.
+            let info = DimensionInfo {
+                dimension_name: packet.dimension_name.to_string(),
+                dimension_type,
+                min_y: dimension.min_y,
+                height: dimension.height,
+                has_skylight: dimension.has_skylight,
+                hashed_seed: packet.hashed_seed as i64,
+                game_mode: packet.game_mode,
+                ..session.dimension.clone()
+            };
+            info!(
+                "Respawned in {} (min_y={}, height={}) in {:?}",
+                info.dimension_name, info.min_y, info.height, info.game_mode
+            );
+
+            session.dimension = info.clone();
+            sender.send(ApplicationEvent::Respawn(info)).await?;
+        }
+        EntitySpawnS2c::ID => {
+            let packet: EntitySpawnS2c = decode_packet(&frame)?;
+            trace!("Entity spawn: entity_id={}, kind={}", packet.entity_id.0, packet.kind.0);
+            sender.send(ApplicationEvent::EntitySpawned {
+                id: packet.entity_id.0,
+                entity: TrackedEntity {
+                    uuid: packet.object_uuid,
+                    kind: packet.kind.0,
+                    position: Vec3::new(packet.position.x as f32, packet.position.y as f32, packet.position.z as f32),
+                },
+            }).await?;
+        }
+        PlayerSpawnS2c::ID => {
+            let packet: PlayerSpawnS2c = decode_packet(&frame)?;
+            trace!("Player spawn: entity_id={}, uuid={}", packet.entity_id.0, packet.player_uuid);
+            sender.send(ApplicationEvent::EntitySpawned {
+                id: packet.entity_id.0,
+                entity: TrackedEntity {
+                    uuid: packet.player_uuid,
+                    kind: PLAYER_ENTITY_KIND,
+                    position: Vec3::new(packet.position.x as f32, packet.position.y as f32, packet.position.z as f32),
+                },
+            }).await?;
+        }
+        ResourcePackSendS2c::ID => {
+            let packet: ResourcePackSendS2c = decode_packet(&frame)?;
+            info!("Server offers resource pack {} (forced: {})", packet.url, packet.forced);
+            sender.send(ApplicationEvent::ResourcePackOffer(ResourcePackOffer {
+                url: packet.url.to_string(),
+                hash: packet.hash.0.to_string(),
+                forced: packet.forced,
+                prompt: packet.prompt_message.map(|message| message.to_string()),
+            })).await?;
+        }
+        WorldTimeUpdateS2c::ID => {
+            let packet: WorldTimeUpdateS2c = decode_packet(&frame)?;
+            trace!("World time: age={}, time_of_day={}", packet.world_age, packet.time_of_day);
+            sender.send(ApplicationEvent::WorldTime {
+                world_age: packet.world_age,
+                time_of_day: packet.time_of_day,
+            }).await?;
+        }
+        CustomPayloadS2c::ID => {
+            let packet: CustomPayloadS2c = decode_packet(&frame)?;
+            let data = packet.data.0 .0;
+            trace!("Plugin message on {} ({} bytes)", packet.channel, data.len());
+            sender.send(ApplicationEvent::PluginMessage {
+                channel: packet.channel.to_string(),
+                data: data.to_vec(),
+            }).await?;
+        }
+        GameStateChangeS2c::ID => {
+            let packet: GameStateChangeS2c = decode_packet(&frame)?;
+            trace!("Game state change: {:?} {}", packet.kind, packet.value);
+            if matches!(packet.kind, GameEventKind::ChangeGameMode) {
+                // The new mode is sent as a float: 0 survival, 1 creative, 2 adventure, 3 spectator.
+                let game_mode = match packet.value as i32 {
+                    1 => GameMode::Creative,
+                    2 => GameMode::Adventure,
+                    3 => GameMode::Spectator,
+                    _ => GameMode::Survival,
+                };
+                sender.send(ApplicationEvent::GameModeChanged(game_mode)).await?;
+            }
+            let change = match packet.kind {
+                GameEventKind::BeginRaining => Some(WeatherChange::BeginRain),
+                GameEventKind::EndRaining => Some(WeatherChange::EndRain),
+                GameEventKind::RainLevelChange => Some(WeatherChange::RainLevel(packet.value)),
+                GameEventKind::ThunderLevelChange => Some(WeatherChange::ThunderLevel(packet.value)),
+                _ => None,
+            };
+            if let Some(change) = change {
+                sender.send(ApplicationEvent::Weather(change)).await?;
+            }
+        }
+        EntitiesDestroyS2c::ID => {
+            let packet: EntitiesDestroyS2c = decode_packet(&frame)?;
+            let ids = packet.entity_ids.iter().map(|id| id.0).collect();
+            sender.send(ApplicationEvent::EntitiesRemoved(ids)).await?;
         }
         PlayerPositionLookS2c::ID => {
-            let packet: PlayerPositionLookS2c =
-                frame.decode().expect("Failed to decode PlayerPositionLookS2c");
-            println!(
+            let packet: PlayerPositionLookS2c = decode_packet(&frame)?;
+            trace!(
                 "Player position look: x={}, y={}, z={}, yaw={}, pitch={}",
                 packet.position.x, packet.position.y, packet.position.z, packet.yaw, packet.pitch
             );
+
+            send_packet(enc, stream, &TeleportConfirmC2s { teleport_id: packet.teleport_id })?;
+            sender.send(ApplicationEvent::PlayerPosition {
+                position: Vec3::new(packet.position.x as f32, packet.position.y as f32, packet.position.z as f32),
+                yaw: packet.yaw,
+                pitch: packet.pitch,
+            }).await?;
         }
         KeepAliveS2c::ID => {
-            let packet: KeepAliveS2c = frame.decode().expect("Failed to decode KeepAliveS2c");
-            println!("KeepAlive received with ID: {}", packet.id);
-
-            // Encode the KeepAliveC2s response
-            enc.clear();
-            enc.append_packet(&KeepAliveC2s { id: packet.id })
-                .expect("Failed to encode KeepAliveC2s");
-
-            let data = enc.take();
+            let packet: KeepAliveS2c = decode_packet(&frame)?;
+            trace!("KeepAlive received with ID: {}", packet.id);
 
-            println!("Encoded KeepAliveC2s packet: {:?}", data);
+            send_packet(enc, stream, &KeepAliveC2s { id: packet.id })?;
+            trace!("Successfully sent KeepAliveC2s with ID: {}", packet.id);
 
-            // Send the packet to the server
-            match stream.write_all(&data) {
-                Ok(_) => {
-                    println!("Successfully sent KeepAliveC2s with ID: {}", packet.id);
-                }
-                Err(e) => {
-                    println!(
-                        "Failed to send KeepAliveC2s with ID: {}. Error: {:?}",
-                        packet.id, e
-                    );
-                    return Err(()); // Handle error
-                }
-            }
-            stream.flush().unwrap();
-            sender.send(ApplicationEvent::Connected).await.unwrap();
+            let now = Instant::now();
+            let interval = session.last_keep_alive.map(|last| now - last);
+            session.last_keep_alive = Some(now);
+            sender.send(ApplicationEvent::KeepAlive { interval }).await?;
         }
         ChatMessageS2c::ID => {
-            let packet: ChatMessageS2c =
-                frame.decode().expect("Failed to decode ChatMessageS2c");
-            println!("Chat message: {}", packet.message);
+            let packet: ChatMessageS2c = decode_packet(&frame)?;
+            info!("Chat message: {}", packet.message);
         }
         DisconnectS2c::ID => {
-            let packet: DisconnectS2c =
-                frame.decode().expect("Failed to decode DisconnectS2c");
-            println!("Disconnected by server: {}", packet.reason);
-            return Err(()); // Exit loop after disconnect
+            let packet: DisconnectS2c = decode_packet(&frame)?;
+            warn!("Disconnected by server: {}", packet.reason);
+            return Err(NetworkError::Kicked(packet.reason.to_string()));
         }
         HealthUpdateS2c::ID => {
-            let packet: HealthUpdateS2c =
-                frame.decode().expect("Failed to decode HealthUpdateS2c");
-            println!(
+            let packet: HealthUpdateS2c = decode_packet(&frame)?;
+            trace!(
                 "Health Update: health={}, saturation={}",
                 packet.health, packet.food_saturation
             );
         }
         ChunkDataS2c::ID => {
-            let packet: ChunkDataS2c = frame.decode().expect("Failed to decode ChunkDataS2c");
+            let packet: ChunkDataS2c = decode_packet(&frame)?;
 
-            println!("Chunk data received");
-            println!("Position: x={}, z={}, count={}", packet.pos.x, packet.pos.z, packet.blocks_and_biomes.len());
+            trace!("Chunk data received: x={}, z={}, count={}", packet.pos.x, packet.pos.z, packet.blocks_and_biomes.len());
+            let sections = parse_chunk_sections(packet.blocks_and_biomes, session.dimension.section_count())
+                .map_err(|source| NetworkError::Decode { packet: ChunkDataS2c::NAME, source })?;
+            let light = parse_light(
+                &packet.sky_light_mask,
+                &packet.block_light_mask,
+                &packet.empty_sky_light_mask,
+                &packet.empty_block_light_mask,
+                &light_arrays(&packet.sky_light_arrays),
+                &light_arrays(&packet.block_light_arrays),
+                session.dimension.section_count() + 2,
+            );
             let data = ChunkBlockData {
                 pos: valence_protocol::ChunkPos { x: packet.pos.x, z: packet.pos.z },
-                blocks: packet.blocks_and_biomes.to_vec(),
+                sections,
+                light,
             };
 
-            sender.send(ApplicationEvent::ChunkData(data)).await.unwrap();
+            sender.send(ApplicationEvent::ChunkData(data)).await?;
+        }
+        LightUpdateS2c::ID => {
+            let packet: LightUpdateS2c = decode_packet(&frame)?;
+            trace!("Light update: x={}, z={}", packet.chunk_x.0, packet.chunk_z.0);
+            let light = parse_light(
+                &packet.sky_light_mask,
+                &packet.block_light_mask,
+                &packet.empty_sky_light_mask,
+                &packet.empty_block_light_mask,
+                &light_arrays(&packet.sky_light_arrays),
+                &light_arrays(&packet.block_light_arrays),
+                session.dimension.section_count() + 2,
+            );
+            sender.send(ApplicationEvent::LightUpdate {
+                pos: IVec2::new(packet.chunk_x.0, packet.chunk_z.0),
+                light,
+            }).await?;
+        }
+        UnloadChunkS2c::ID => {
+            let packet: UnloadChunkS2c = decode_packet(&frame)?;
+            trace!("Unload chunk: x={}, z={}", packet.pos.x, packet.pos.z);
+            sender.send(ApplicationEvent::UnloadChunk(IVec2::new(packet.pos.x, packet.pos.z))).await?;
+        }
+        ChunkDeltaUpdateS2c::ID => {
+            let packet: ChunkDeltaUpdateS2c = decode_packet(&frame)?;
+            let section = packet.chunk_sect_pos;
+            let origin = IVec3::new(section.x, section.y, section.z) * 16;
+            let updates = packet
+                .blocks
+                .iter()
+                .map(|entry| {
+                    // State id in the high bits, then 4 bits each of x, z, y.
+                    let entry = entry.0 as u64;
+                    let local = IVec3::new((entry >> 8 & 0xf) as i32, (entry & 0xf) as i32, (entry >> 4 & 0xf) as i32);
+                    let state = BlockState::from_raw((entry >> 12) as u16).unwrap_or(BlockState::AIR);
+                    (origin + local, state)
+                })
+                .collect();
+            sender.send(ApplicationEvent::BlockUpdates(updates)).await?;
         }
         PlayerSpawnPositionS2c::ID => {
             // let packet: PlayerSpawnPositionS2c =
@@ -170,56 +563,57 @@ async fn process_packet(
             //     "Player spawn position: x={}, y={}, z={}",
             //     packet.position.x, packet.position.y, packet.position.z
             // );
-            println!("PlayerSpawnPositionS2c");
+            trace!("PlayerSpawnPositionS2c");
         }
         PlayerAbilitiesS2c::ID => {
             // heap_stats();
-            let packet: PlayerAbilitiesS2c =
-                frame.decode().expect("Failed to decode PlayerAbilitiesS2c");
-            println!("Player abilities: {:?}", packet.flags);
+            let packet: PlayerAbilitiesS2c = decode_packet(&frame)?;
+            trace!("Player abilities: {:?}", packet.flags);
         }
         EntityStatusS2c::ID => {
-            let packet: EntityStatusS2c =
-                frame.decode().expect("Failed to decode EntityStatusS2c");
-            println!("Entity status: entity_id={}, status={}", packet.entity_id, packet.entity_status);
+            let packet: EntityStatusS2c = decode_packet(&frame)?;
+            trace!("Entity status: entity_id={}, status={}", packet.entity_id, packet.entity_status);
         }
         EntityAttributesS2c::ID => {
-            let packet: EntityAttributesS2c =
-                frame.decode().expect("Failed to decode EntityAttributesS2c");
-            println!("Entity attributes: entity_id={:?}, attributes={:?}", packet.entity_id, packet.properties);
+            let packet: EntityAttributesS2c = decode_packet(&frame)?;
+            trace!("Entity attributes: entity_id={:?}, attributes={:?}", packet.entity_id, packet.properties);
         }
         UpdateSelectedSlotS2c::ID => {
-            let packet: UpdateSelectedSlotS2c =
-                frame.decode().expect("Failed to decode UpdateSelectedSlotS2c");
-            println!("Selected slot updated: slot={}", packet.slot);
+            let packet: UpdateSelectedSlotS2c = decode_packet(&frame)?;
+            trace!("Selected slot updated: slot={}", packet.slot);
         }
         PlayerListS2c::ID => {
-            let packet: PlayerListS2c =
-                frame.decode().expect("Failed to decode PlayerListS2c");
-            println!("Player list: {:?}", packet.entries);
+            let packet: PlayerListS2c = decode_packet(&frame)?;
+            trace!("Player list: {:?}", packet.entries);
+
+            // The server measures our round trip from the keep-alive echoes and
+            // publishes it in the player list, which is the best estimate we get.
+            if packet.actions.update_latency() {
+                if let Some(own) = packet.entries.iter().find(|entry| entry.player_uuid == session.player_uuid) {
+                    let latency = Duration::from_millis(own.ping.max(0) as u64);
+                    sender.send(ApplicationEvent::Latency(latency)).await?;
+                }
+            }
         }
         ScreenHandlerSlotUpdateS2c::ID => {
-            println!("Received ScreenHandlerSlotUpdateS2c.");
+            trace!("Received ScreenHandlerSlotUpdateS2c.");
         }
         AdvancementUpdateS2c::ID => {
-            let packet: AdvancementUpdateS2c =
-                frame.decode().expect("Failed to decode AdvancementUpdateS2c");
-            println!("Advancement update: {:?}", packet.identifiers);
+            let packet: AdvancementUpdateS2c = decode_packet(&frame)?;
+            trace!("Advancement update: {:?}", packet.identifiers);
         }
         CommandTreeS2c::ID => {
-            println!("Received CommandTreeS2c.");
+            trace!("Received CommandTreeS2c.");
         }
         SynchronizeTagsS2c::ID => {
-            println!("Received SynchronizeTagsS2c.");
-            let packet: SynchronizeTagsS2c =
-                frame.decode().expect("Failed to decode SynchronizeTagsS2c");
+            trace!("Received SynchronizeTagsS2c.");
+            let _packet: SynchronizeTagsS2c = decode_packet(&frame)?;
             // println!("Tags: {:?}", packet.groups);
         }
         GameMessageS2c::ID => {
-            let packet: GameMessageS2c =
-                frame.decode().expect("Failed to decode GameMessageS2c");
+            let packet: GameMessageS2c = decode_packet(&frame)?;
             let received_message = packet.chat.to_string();
-            println!("Received message: {:?}", received_message);
+            info!("Received message: {:?}", received_message);
 
             if received_message.contains("How are you?") {
                 let message = ChatMessageC2s {
@@ -231,55 +625,42 @@ async fn process_packet(
                     acknowledgement: Default::default(),
                 };
 
-                enc.clear();
-                enc.append_packet(&message)
-                    .expect("Failed to encode ChatMessageC2s");
-                let data = enc.take();
-
-                println!("Sending ChatMessageC2s packet: {:?}", data);
-
-                match stream.write_all(&data) {
-                    Ok(_) => {
-                        println!("Chat message sent: 'ahoj'");
-                    }
-                    Err(e) => {
-                        println!("Failed to send chat message. Error: {:?}", e);
-                    }
-                }
-                stream.flush().unwrap();
+                send_packet(enc, stream, &message)?;
+                debug!("Chat message sent");
             }
         }
         EntitySetHeadYawS2c::ID => {
-            println!("EntitySetHeadYawS2c");
+            trace!("EntitySetHeadYawS2c");
         }
         RotateS2c::ID => {
-            println!("RotateS2c");
+            trace!("RotateS2c");
+        }
+        BlockBreakingProgressS2c::ID => {
+            let packet: BlockBreakingProgressS2c = decode_packet(&frame)?;
+            sender.send(ApplicationEvent::BreakingProgress {
+                breaker: packet.entity_id.0,
+                block: IVec3::new(packet.position.x, packet.position.y, packet.position.z),
+                stage: packet.destroy_stage,
+            }).await?;
         }
         BlockUpdateS2c::ID => {
-            println!("BlockUpdateS2c");
-
-            // Attempt to decode the packet
-            let packet: BlockUpdateS2c = match frame.decode() {
-                Ok(decoded_packet) => decoded_packet,
-                Err(err) => {
-                    println!("Failed to decode BlockUpdateS2c: {:?}", err);
-                    return Err(()); // Skip further processing for this packet
-                }
-            };
+            trace!("BlockUpdateS2c");
+
+            let packet: BlockUpdateS2c = decode_packet(&frame)?;
+            let position = IVec3::new(packet.position.x, packet.position.y, packet.position.z);
+            sender.send(ApplicationEvent::BlockUpdates(vec![(position, packet.block_id)])).await?;
 
             // Safely get the "Lit" property and handle potential absence
             if let Some(PropValue::True) = packet.block_id.get(PropName::Lit) {
-                println!("Block is lit, turning on LED.");
-                sender.send(ApplicationEvent::LampOn).await.unwrap();
+                debug!("Block is lit, turning on LED.");
+                sender.send(ApplicationEvent::LampOn).await?;
             } else {
-                println!("Block is not lit, turning off LED.");
-                sender.send(ApplicationEvent::LampOff).await.unwrap();
+                debug!("Block is not lit, turning off LED.");
+                sender.send(ApplicationEvent::LampOff).await?;
             }
         }
 
-        _ => println!("Unhandled packet ID: 0x{:X}", frame.id),
+        _ => trace!("Unhandled packet ID: 0x{:X}", frame.id),
     }
-    // heap_stats();
     Ok(())
-
-}
\ No newline at end of file
+}
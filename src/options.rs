@@ -0,0 +1,153 @@
+use std::fs;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use valence_protocol::Encode;
+use crate::connection::ServerConnection;
+use crate::events::ClientCommand;
+
+/// Player options, kept next to the server list.
+const OPTIONS_FILE: &str = "options.json";
+
+/// Plugin channel the client names itself on, which some server plugins check.
+const BRAND_CHANNEL: &str = "minecraft:brand";
+
+/// Render distance limits, in chunks, as in vanilla.
+const MIN_RENDER_DISTANCE: u8 = 2;
+const MAX_RENDER_DISTANCE: u8 = 32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ChatVisibility {
+    #[default]
+    Full,
+    CommandsOnly,
+    Hidden,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MainHand {
+    Left,
+    #[default]
+    Right,
+}
+
+/// Which outer skin layers other players see.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct SkinParts {
+    pub(crate) cape: bool,
+    pub(crate) jacket: bool,
+    pub(crate) left_sleeve: bool,
+    pub(crate) right_sleeve: bool,
+    pub(crate) left_pants_leg: bool,
+    pub(crate) right_pants_leg: bool,
+    pub(crate) hat: bool,
+}
+
+impl Default for SkinParts {
+    fn default() -> Self {
+        Self {
+            cape: true,
+            jacket: true,
+            left_sleeve: true,
+            right_sleeve: true,
+            left_pants_leg: true,
+            right_pants_leg: true,
+            hat: true,
+        }
+    }
+}
+
+/// Player options. Everything except the render distance only matters to the server,
+/// which gets it in `ClientSettingsC2s`.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ClientOptions {
+    /// Radius in chunks around the camera that is drawn; also sent as our view distance.
+    pub(crate) render_distance: u8,
+    /// How far away blocks can be targeted. Vanilla reaches 4.5 blocks, 5 in creative,
+    /// and servers refuse digging much further than that.
+    pub(crate) reach: f32,
+    pub(crate) locale: String,
+    pub(crate) chat_visibility: ChatVisibility,
+    pub(crate) chat_colors: bool,
+    pub(crate) skin_parts: SkinParts,
+    pub(crate) main_hand: MainHand,
+    pub(crate) text_filtering: bool,
+    /// Whether we may show up in the server's player list in status pings.
+    pub(crate) allow_server_listings: bool,
+    /// What we call ourselves on the `minecraft:brand` channel.
+    pub(crate) brand: String,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            render_distance: 10,
+            reach: 4.5,
+            locale: "en_us".to_string(),
+            chat_visibility: ChatVisibility::default(),
+            chat_colors: true,
+            skin_parts: SkinParts::default(),
+            main_hand: MainHand::default(),
+            text_filtering: false,
+            allow_server_listings: true,
+            brand: env!("CARGO_PKG_NAME").to_string(),
+        }
+    }
+}
+
+impl ClientOptions {
+    pub(crate) fn load() -> Self {
+        let Ok(json) = fs::read_to_string(OPTIONS_FILE) else {
+            return Self::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable {}: {}", OPTIONS_FILE, e);
+            Self::default()
+        })
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(OPTIONS_FILE, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Failed to save {}: {}", OPTIONS_FILE, e);
+        }
+    }
+}
+
+/// `+` and `-` change the render distance by a chunk.
+pub(crate) fn adjust_render_distance(keyboard_input: Res<ButtonInput<KeyCode>>, mut options: ResMut<ClientOptions>) {
+    let distance = if keyboard_input.just_pressed(KeyCode::Equal) {
+        options.render_distance.saturating_add(1)
+    } else if keyboard_input.just_pressed(KeyCode::Minus) {
+        options.render_distance.saturating_sub(1)
+    } else {
+        return;
+    };
+    let distance = distance.clamp(MIN_RENDER_DISTANCE, MAX_RENDER_DISTANCE);
+    if distance != options.render_distance {
+        info!("Render distance set to {} chunks", distance);
+        options.render_distance = distance;
+        options.save();
+    }
+}
+
+/// Introduces us after `GameJoinS2c` the way vanilla does: brand first, then settings.
+pub(crate) fn send_join_info(connection: &ServerConnection, options: &ClientOptions) {
+    let mut brand = Vec::new();
+    options.brand.as_str().encode(&mut brand).expect("encoding into a Vec can't fail");
+    connection.send(ClientCommand::PluginMessage { channel: BRAND_CHANNEL.to_string(), data: brand });
+    connection.send(ClientCommand::ClientSettings(options.clone()));
+}
+
+/// Resends the settings when options change while playing, e.g. so the server sends
+/// chunks out to a new render distance. The initial ones go out with the join.
+pub(crate) fn send_client_settings(options: Res<ClientOptions>, connection: Res<ServerConnection>) {
+    if options.is_changed() && !options.is_added() {
+        connection.send(ClientCommand::ClientSettings(options.clone()));
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeSet;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use crate::connection::ServerConnection;
+use crate::events::ClientCommand;
+
+/// Comma-separated plugin channels to subscribe to at startup, e.g. `ourteam:debug`.
+pub(crate) const PLUGIN_CHANNELS_ENV: &str = "BEVY_CLIENT_PLUGIN_CHANNELS";
+
+/// Channel on which clients tell the server which plugin channels they listen to.
+const REGISTER_CHANNEL: &str = "minecraft:register";
+
+/// Plugin channels whose custom payloads are delivered as [`PluginMessageReceived`].
+/// Messages on other channels are dropped.
+#[derive(Resource, Default)]
+pub(crate) struct PluginChannels(BTreeSet<String>);
+
+impl PluginChannels {
+    pub(crate) fn from_env() -> Self {
+        let mut channels = Self::default();
+        if let Ok(list) = std::env::var(PLUGIN_CHANNELS_ENV) {
+            for channel in list.split(',').map(str::trim).filter(|channel| !channel.is_empty()) {
+                channels.subscribe(channel);
+            }
+        }
+        channels
+    }
+
+    /// Starts delivering messages on `channel`, named like `namespace:path`.
+    pub(crate) fn subscribe(&mut self, channel: &str) {
+        if self.0.insert(channel.to_string()) {
+            info!("Subscribed to plugin channel {}", channel);
+        }
+    }
+
+    pub(crate) fn is_subscribed(&self, channel: &str) -> bool {
+        self.0.contains(channel)
+    }
+
+    /// The message announcing our channels to the server, a NUL-separated list like vanilla's.
+    fn registration(&self) -> Option<SendPluginMessage> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let names: Vec<&str> = self.0.iter().map(String::as_str).collect();
+        Some(SendPluginMessage { channel: REGISTER_CHANNEL.to_string(), data: names.join("\0").into_bytes() })
+    }
+}
+
+/// A custom payload from the server on a subscribed channel.
+#[derive(Event, Clone, Debug)]
+pub(crate) struct PluginMessageReceived {
+    pub(crate) channel: String,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Sends a custom payload to the server; write these from any system.
+#[derive(Event, Clone, Debug)]
+pub(crate) struct SendPluginMessage {
+    pub(crate) channel: String,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Hands incoming custom payloads to the systems subscribed to their channel.
+#[derive(SystemParam)]
+pub(crate) struct PluginMessageInbox<'w> {
+    channels: Res<'w, PluginChannels>,
+    received: EventWriter<'w, PluginMessageReceived>,
+    outgoing: EventWriter<'w, SendPluginMessage>,
+}
+
+impl PluginMessageInbox<'_> {
+    pub(crate) fn deliver(&mut self, channel: String, data: Vec<u8>) {
+        if self.channels.is_subscribed(&channel) {
+            self.received.send(PluginMessageReceived { channel, data });
+        } else {
+            trace!("Dropping {} byte plugin message on unsubscribed channel {}", data.len(), channel);
+        }
+    }
+
+    /// Tells a server we just joined which channels we listen to.
+    pub(crate) fn register(&mut self) {
+        if let Some(message) = self.channels.registration() {
+            self.outgoing.send(message);
+        }
+    }
+}
+
+pub(crate) fn send_plugin_messages(mut messages: EventReader<SendPluginMessage>, connection: Res<ServerConnection>) {
+    for message in messages.read() {
+        connection.send(ClientCommand::PluginMessage { channel: message.channel.clone(), data: message.data.clone() });
+    }
+}
+
+/// Registers channels subscribed to after joining; the rest go out with the join.
+pub(crate) fn register_new_channels(channels: Res<PluginChannels>, mut outgoing: EventWriter<SendPluginMessage>) {
+    if channels.is_changed() && !channels.is_added() {
+        if let Some(message) = channels.registration() {
+            outgoing.send(message);
+        }
+    }
+}
+
+/// Logs what arrives on subscribed channels, for watching server plugins from tooling.
+pub(crate) fn log_plugin_messages(mut messages: EventReader<PluginMessageReceived>) {
+    for message in messages.read() {
+        info!(
+            "Plugin message on {} ({} bytes): {}",
+            message.channel,
+            message.data.len(),
+            String::from_utf8_lossy(&message.data)
+        );
+    }
+}
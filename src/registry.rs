@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use bevy::prelude::Resource;
+use valence_protocol::nbt::{Compound, List, Value};
+
+/// The parts of a dimension type the client needs to lay out and light chunks.
+#[derive(Clone, Debug)]
+pub(crate) struct DimensionType {
+    pub(crate) min_y: i32,
+    pub(crate) height: i32,
+    pub(crate) has_skylight: bool,
+    pub(crate) ambient_light: f32,
+}
+
+/// Biome properties used to tint terrain.
+#[derive(Clone, Debug)]
+pub(crate) struct Biome {
+    pub(crate) name: String,
+    pub(crate) temperature: f32,
+    pub(crate) downfall: f32,
+    pub(crate) has_precipitation: bool,
+    pub(crate) sky_color: u32,
+    pub(crate) fog_color: u32,
+    pub(crate) water_color: u32,
+    /// Unset for most biomes; the vanilla client then derives it from temperature and downfall.
+    pub(crate) grass_color: Option<u32>,
+    pub(crate) foliage_color: Option<u32>,
+}
+
+/// What we keep from the registry codec sent in `GameJoinS2c`.
+#[derive(Resource, Clone, Debug, Default)]
+pub(crate) struct Registries {
+    pub(crate) dimension_types: HashMap<String, DimensionType>,
+    /// Indexed by the biome's network id, as used in chunk data.
+    pub(crate) biomes: Vec<Biome>,
+    pub(crate) damage_types: Vec<String>,
+}
+
+impl Registries {
+    pub(crate) fn from_codec(codec: &Compound) -> Self {
+        let mut registries = Registries::default();
+
+        for (name, _, element) in registry_entries(codec, "minecraft:dimension_type") {
+            registries.dimension_types.insert(name, DimensionType {
+                min_y: get_int(element, "min_y").unwrap_or(0),
+                height: get_int(element, "height").unwrap_or(256),
+                has_skylight: get_bool(element, "has_skylight").unwrap_or(true),
+                ambient_light: get_float(element, "ambient_light").unwrap_or(0.0),
+            });
+        }
+
+        let mut biomes: Vec<(usize, Biome)> = registry_entries(codec, "minecraft:worldgen/biome")
+            .map(|(name, id, element)| {
+                let effects = match element.get("effects") {
+                    Some(Value::Compound(effects)) => Some(effects),
+                    _ => None,
+                };
+                let color = |key: &str| effects.and_then(|e| get_int(e, key)).map(|c| c as u32);
+                (id, Biome {
+                    name,
+                    temperature: get_float(element, "temperature").unwrap_or(0.5),
+                    downfall: get_float(element, "downfall").unwrap_or(0.5),
+                    has_precipitation: get_bool(element, "has_precipitation").unwrap_or(true),
+                    sky_color: color("sky_color").unwrap_or(0x78a7ff),
+                    fog_color: color("fog_color").unwrap_or(0xc0d8ff),
+                    water_color: color("water_color").unwrap_or(0x3f76e4),
+                    grass_color: color("grass_color"),
+                    foliage_color: color("foliage_color"),
+                })
+            })
+            .collect();
+        biomes.sort_by_key(|(id, _)| *id);
+        registries.biomes = biomes.into_iter().map(|(_, biome)| biome).collect();
+
+        registries.damage_types = registry_entries(codec, "minecraft:damage_type")
+            .map(|(name, _, _)| name)
+            .collect();
+
+        registries
+    }
+
+    pub(crate) fn biome(&self, id: u16) -> Option<&Biome> {
+        self.biomes.get(id as usize)
+    }
+}
+
+/// Iterates `(name, id, element)` of one registry in the codec.
+fn registry_entries<'a>(codec: &'a Compound, registry: &str) -> impl Iterator<Item = (String, usize, &'a Compound)> {
+    let entries = match codec.get(registry) {
+        Some(Value::Compound(registry)) => match registry.get("value") {
+            Some(Value::List(List::Compound(entries))) => entries.as_slice(),
+            _ => &[],
+        },
+        _ => &[],
+    };
+
+    entries.iter().enumerate().filter_map(|(index, entry)| {
+        let name = match entry.get("name") {
+            Some(Value::String(name)) => name.clone(),
+            _ => return None,
+        };
+        let id = get_int(entry, "id").map_or(index, |id| id as usize);
+        match entry.get("element") {
+            Some(Value::Compound(element)) => Some((name, id, element)),
+            _ => None,
+        }
+    })
+}
+
+fn get_int(compound: &Compound, key: &str) -> Option<i32> {
+    match compound.get(key)? {
+        Value::Byte(v) => Some(*v as i32),
+        Value::Short(v) => Some(*v as i32),
+        Value::Int(v) => Some(*v),
+        Value::Long(v) => Some(*v as i32),
+        _ => None,
+    }
+}
+
+fn get_float(compound: &Compound, key: &str) -> Option<f32> {
+    match compound.get(key)? {
+        Value::Float(v) => Some(*v),
+        Value::Double(v) => Some(*v as f32),
+        _ => get_int(compound, key).map(|v| v as f32),
+    }
+}
+
+fn get_bool(compound: &Compound, key: &str) -> Option<bool> {
+    get_int(compound, key).map(|v| v != 0)
+}
@@ -1,38 +1,224 @@
 use bevy::asset::Assets;
 use bevy::color::Color;
-use bevy::math::Vec3;
-use bevy::pbr::{PbrBundle, StandardMaterial};
-use bevy::prelude::{default, Camera3dBundle, Commands, Cuboid, Mesh, PositionType, ResMut, Style, Text, TextBundle, TextStyle, Transform, Val};
-use bevy::render::view::NoFrustumCulling;
+use bevy::log::debug;
+use bevy::math::{Affine2, Vec2, Vec3};
+use bevy::pbr::{FogFalloff, FogSettings, PbrBundle, StandardMaterial};
+use std::collections::HashMap;
+use bevy::prelude::{default, AlignItems, AlphaMode, SpatialBundle, BackgroundColor, BuildChildren, Camera3d, Camera3dBundle, ClearColor, IVec2, JustifyContent, NodeBundle, Visibility, ZIndex, Commands, Component, Cuboid, DespawnRecursiveExt, DetectChanges, Entity, Handle, IVec3, Image, Mesh, PositionType, Query, Res, ResMut, Resource, Style, Text, TextBundle, TextStyle, Time, Transform, Val, With};
+use crate::connection::NetworkStats;
+use crate::options::ClientOptions;
+use crate::blocks::{BlockAppearances, Fluid};
+use crate::mesher::{build_section_meshes, TRANSLUCENT_PIVOT};
+use crate::registry::Registries;
+use crate::world::{ChunkStore, SECTION_SIZE};
 use crate::GlowingCube;
 
-pub(crate) fn setup_ui(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<StandardMaterial>>) {
+/// HUD line showing the connection status message.
+#[derive(Component)]
+pub(crate) struct StatusText;
+
+/// HUD line showing latency and keep-alive timing.
+#[derive(Component)]
+pub(crate) struct NetworkStatsText;
+
+/// Mesh of one chunk section, at (chunk x, section index, chunk z).
+#[derive(Component)]
+pub(crate) struct ChunkSectionMesh(pub(crate) IVec3);
+
+/// Shared materials for all chunk meshes; colors come from the vertices, multiplied
+/// with the block atlas when a resource pack is loaded.
+#[derive(Resource)]
+pub(crate) struct ChunkMaterials {
+    solid: Handle<StandardMaterial>,
+    /// Alpha-masked, for leaves, plants and plain glass.
+    cutout: Handle<StandardMaterial>,
+    /// Alpha-blended, for water, ice and stained glass.
+    translucent: Handle<StandardMaterial>,
+    emissive: Handle<StandardMaterial>,
+    /// Flowing fluids, each with its own scrolling flow texture.
+    flowing_water: Handle<StandardMaterial>,
+    flowing_lava: Handle<StandardMaterial>,
+}
+
+impl ChunkMaterials {
+    /// Points the materials at the current atlas and fluid textures.
+    pub(crate) fn set_textures(&self, materials: &mut Assets<StandardMaterial>, appearances: &BlockAppearances) {
+        for handle in [&self.solid, &self.cutout, &self.translucent, &self.emissive] {
+            if let Some(material) = materials.get_mut(handle) {
+                material.base_color_texture = appearances.atlas.clone();
+            }
+        }
+        for (handle, texture) in [&self.flowing_water, &self.flowing_lava].into_iter().zip(&appearances.fluid_flow) {
+            if let Some(material) = materials.get_mut(handle) {
+                material.base_color_texture = texture.clone();
+            }
+        }
+    }
+}
+
+/// How far flowing fluid textures scroll per second, in texture heights.
+const WATER_FLOW_SPEED: f32 = 0.5;
+const LAVA_FLOW_SPEED: f32 = 0.15;
+
+/// Which entity holds the meshes of each section.
+#[derive(Resource, Default)]
+pub(crate) struct SectionEntities(HashMap<IVec3, Entity>);
+
+impl SectionEntities {
+    /// Despawns every section mesh, e.g. when the world is replaced.
+    pub(crate) fn clear(&mut self, commands: &mut Commands) {
+        for (_, entity) in self.0.drain() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Full-screen overlay shown while the world around the player is still arriving.
+#[derive(Component)]
+pub(crate) struct LoadingScreen;
+
+/// Set on join and respawn, cleared once the chunk the player stands in has loaded.
+#[derive(Resource, Default)]
+pub(crate) struct LoadingTerrain {
+    pub(crate) active: bool,
+    /// Where the server put us; unknown until the first position packet.
+    pub(crate) spawn: Option<Vec3>,
+}
+
+/// Meshing is spread over frames so a burst of chunks doesn't stall rendering.
+const SECTIONS_MESHED_PER_FRAME: usize = 32;
+
+pub(crate) fn setup_ui(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    appearances: Res<BlockAppearances>,
+) {
+    debug!("Setting up camera and HUD");
+
     // 3D Camera
     commands.spawn((
         Camera3dBundle {
             transform: Transform::from_xyz(16.0, 16.0, 16.0).looking_at(Vec3::new(8.0, 0.0, 8.0), Vec3::Y),
             ..default()
         },
-        NoFrustumCulling,
+        FogSettings::default(),
     ));
 
     // Text HUD
-    commands.spawn(TextBundle {
-        text: Text::from_section(
-            "Connecting...",
-            TextStyle {
-                font_size: 30.0,
-                color: Color::WHITE,
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "Connecting...",
+                TextStyle {
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute, // Important for HUD
+                top: Val::Px(10.0),
+                left: Val::Px(10.0),
                 ..default()
             },
-        ),
-        style: Style {
-            position_type: PositionType::Absolute, // Important for HUD
-            top: Val::Px(10.0),
-            left: Val::Px(10.0),
             ..default()
         },
-        ..default()
+        StatusText,
+    ));
+
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                right: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        },
+        NetworkStatsText,
+    ));
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::srgb(0.1, 0.08, 0.06)),
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Loading terrain...",
+                TextStyle {
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ));
+        });
+
+    commands.insert_resource(ChunkMaterials {
+        solid: materials.add(StandardMaterial {
+            base_color_texture: appearances.atlas.clone(),
+            perceptual_roughness: 1.0,
+            ..default()
+        }),
+        cutout: materials.add(StandardMaterial {
+            base_color_texture: appearances.atlas.clone(),
+            perceptual_roughness: 1.0,
+            alpha_mode: AlphaMode::Mask(0.5),
+            cull_mode: None,
+            double_sided: true,
+            ..default()
+        }),
+        translucent: materials.add(StandardMaterial {
+            base_color_texture: appearances.atlas.clone(),
+            perceptual_roughness: 1.0,
+            alpha_mode: AlphaMode::Blend,
+            cull_mode: None,
+            double_sided: true,
+            ..default()
+        }),
+        // Torches and the like glow too, so their transparent texels are cut out.
+        emissive: materials.add(StandardMaterial {
+            base_color_texture: appearances.atlas.clone(),
+            unlit: true,
+            alpha_mode: AlphaMode::Mask(0.5),
+            ..default()
+        }),
+        flowing_water: materials.add(StandardMaterial {
+            base_color_texture: appearances.fluid_flow[Fluid::Water as usize].clone(),
+            perceptual_roughness: 1.0,
+            alpha_mode: AlphaMode::Blend,
+            cull_mode: None,
+            double_sided: true,
+            ..default()
+        }),
+        flowing_lava: materials.add(StandardMaterial {
+            base_color_texture: appearances.fluid_flow[Fluid::Lava as usize].clone(),
+            unlit: true,
+            ..default()
+        }),
     });
 
     commands.spawn((
@@ -46,3 +232,161 @@ pub(crate) fn setup_ui(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>,
     ));
 
 }
+
+pub(crate) fn update_network_hud(
+    stats: Res<NetworkStats>,
+    mut text_query: Query<&mut Text, With<NetworkStatsText>>,
+) {
+    if !stats.is_changed() {
+        return;
+    }
+
+    let latency = match stats.latency {
+        Some(latency) => format!("{} ms", latency.as_millis()),
+        None => "-".to_string(),
+    };
+    let keep_alive = match stats.keep_alive_interval {
+        Some(interval) => format!("{:.1} s", interval.as_secs_f32()),
+        None => "-".to_string(),
+    };
+
+    for mut text in &mut text_query {
+        text.sections[0].value = format!("Ping: {}\nKeep-alive: {}", latency, keep_alive);
+    }
+}
+
+pub(crate) fn update_chunk_meshes(
+    mut commands: Commands,
+    mut store: ResMut<ChunkStore>,
+    registries: Res<Registries>,
+    appearances: Res<BlockAppearances>,
+    materials: Res<ChunkMaterials>,
+    mut section_entities: ResMut<SectionEntities>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if store.dirty.is_empty() {
+        return;
+    }
+
+    let batch: Vec<IVec3> = store.dirty.iter().take(SECTIONS_MESHED_PER_FRAME).copied().collect();
+    for section_pos in batch {
+        store.dirty.remove(&section_pos);
+
+        if let Some(entity) = section_entities.0.remove(&section_pos) {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        let section_meshes = build_section_meshes(&store, section_pos, &registries, &appearances);
+        // Bevy sorts blended meshes back to front by their origin, so the translucent
+        // mesh is built around the section's center rather than its corner.
+        let parts: Vec<_> = [
+            (section_meshes.solid, &materials.solid, Vec3::ZERO),
+            (section_meshes.cutout, &materials.cutout, Vec3::ZERO),
+            (section_meshes.translucent, &materials.translucent, Vec3::splat(TRANSLUCENT_PIVOT)),
+            (section_meshes.emissive, &materials.emissive, Vec3::ZERO),
+            (section_meshes.flowing_water, &materials.flowing_water, Vec3::splat(TRANSLUCENT_PIVOT)),
+            (section_meshes.flowing_lava, &materials.flowing_lava, Vec3::ZERO),
+        ]
+        .into_iter()
+        .filter_map(|(mesh, material, offset)| Some((mesh?, material.clone(), offset)))
+        .collect();
+        if parts.is_empty() {
+            continue;
+        }
+
+        let origin = Vec3::new(
+            (section_pos.x * SECTION_SIZE) as f32,
+            (store.min_y() + section_pos.y * SECTION_SIZE) as f32,
+            (section_pos.z * SECTION_SIZE) as f32,
+        );
+        let entity = commands
+            .spawn((SpatialBundle::from_transform(Transform::from_translation(origin)), ChunkSectionMesh(section_pos)))
+            .with_children(|parent| {
+                for (mesh, material, offset) in parts {
+                    parent.spawn(PbrBundle {
+                        mesh: meshes.add(mesh),
+                        material,
+                        transform: Transform::from_translation(offset),
+                        ..default()
+                    });
+                }
+            })
+            .id();
+        section_entities.0.insert(section_pos, entity);
+    }
+}
+
+/// Scrolls the flow textures so flowing fluids move downhill and down waterfalls.
+pub(crate) fn animate_fluids(
+    time: Res<Time>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let seconds = time.elapsed_seconds();
+    for (handle, speed) in [(&chunk_materials.flowing_water, WATER_FLOW_SPEED), (&chunk_materials.flowing_lava, LAVA_FLOW_SPEED)] {
+        if let Some(material) = materials.get_mut(handle) {
+            material.uv_transform = Affine2::from_translation(Vec2::new(0.0, -(seconds * speed).fract()));
+        }
+    }
+}
+
+/// Hides sections beyond the render distance and fades the world into the sky before
+/// that, so chunks don't visibly pop in and out at the edge.
+pub(crate) fn update_view_distance(
+    options: Res<ClientOptions>,
+    clear_color: Res<ClearColor>,
+    mut camera_query: Query<(&Transform, &mut FogSettings), With<Camera3d>>,
+    mut section_query: Query<(&ChunkSectionMesh, &mut Visibility)>,
+) {
+    let Ok((camera, mut fog)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    let end = options.render_distance as f32 * SECTION_SIZE as f32;
+    // Vanilla starts fogging a tenth of the distance early, within limits.
+    let start = end - (end / 10.0).clamp(4.0, 64.0);
+    if fog.color != clear_color.0 {
+        fog.color = clear_color.0;
+    }
+    if !matches!(fog.falloff, FogFalloff::Linear { start: s, end: e } if s == start && e == end) {
+        fog.falloff = FogFalloff::Linear { start, end };
+    }
+
+    let camera_chunk = (camera.translation / SECTION_SIZE as f32).floor().as_ivec3();
+    let max_distance = options.render_distance as i32;
+    for (section, mut visibility) in &mut section_query {
+        let (dx, dz) = (section.0.x - camera_chunk.x, section.0.z - camera_chunk.z);
+        let wanted = if dx * dx + dz * dz <= max_distance * max_distance {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+}
+
+pub(crate) fn update_loading_screen(
+    mut loading: ResMut<LoadingTerrain>,
+    store: Res<ChunkStore>,
+    mut screen_query: Query<&mut Visibility, With<LoadingScreen>>,
+) {
+    if loading.active {
+        if let Some(spawn) = loading.spawn {
+            let chunk = IVec2::new(spawn.x.floor() as i32 >> 4, spawn.z.floor() as i32 >> 4);
+            if store.chunk(chunk).is_some() {
+                debug!("Terrain around {} loaded", spawn);
+                loading.active = false;
+            }
+        }
+    }
+
+    if !loading.is_changed() {
+        return;
+    }
+    let visibility = if loading.active { Visibility::Visible } else { Visibility::Hidden };
+    for mut screen in &mut screen_query {
+        *screen = visibility;
+    }
+}
@@ -0,0 +1,653 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use bevy::color::Color;
+use bevy::log::{debug, warn};
+use bevy::prelude::Image;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::{CompressedImageFormats, ImageAddressMode, ImageSampler, ImageSamplerDescriptor, ImageType};
+use serde::Deserialize;
+use serde_json::Value;
+use valence_protocol::block::{BlockKind, PropName, PropValue};
+use valence_protocol::BlockState;
+use zip::ZipArchive;
+use crate::block_models::ModelBox;
+use crate::blocks::{AtlasRect, BlockElement, FaceAppearance, Fluid, FACE_TOP};
+
+const TEXTURE_DIR: &str = "assets/minecraft/textures/block/";
+const MODEL_DIR: &str = "assets/minecraft/models/";
+const BLOCKSTATE_DIR: &str = "assets/minecraft/blockstates/";
+/// Model parents nest a few levels deep in vanilla; anything deeper is a cycle.
+const MAX_MODEL_DEPTH: usize = 16;
+/// Zip entries can claim any size, so reads stop here: no vanilla file comes close.
+const MAX_ZIP_ENTRY_SIZE: u64 = 16 * 1024 * 1024;
+/// Total bytes unpacked from one zip, well above any real pack's block assets.
+const MAX_ZIP_TOTAL_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Face names in mesher order: +X, -X, +Y, -Y, +Z, -Z.
+const FACE_NAMES: [&str; 6] = ["east", "west", "up", "down", "south", "north"];
+/// A whole texture, in texture pixels.
+const FULL_UV: [f32; 4] = [0.0, 0.0, 16.0, 16.0];
+/// Outward normals in mesher order.
+const NORMALS: [[f32; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+/// Directions texture u and v run along on each face, as laid out by `mesher::face_uv`.
+const U_AXES: [[f32; 3]; 6] = [
+    [0.0, 0.0, -1.0],
+    [0.0, 0.0, 1.0],
+    [1.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+];
+const V_AXES: [[f32; 3]; 6] = [
+    [0.0, -1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+    [0.0, -1.0, 0.0],
+    [0.0, -1.0, 0.0],
+];
+
+/// Block textures from a resource pack, stitched into one atlas and resolved per state.
+pub(crate) struct ResourcePack {
+    pub(crate) atlas: Image,
+    pub(crate) white: AtlasRect,
+    pub(crate) elements: HashMap<BlockState, Vec<BlockElement>>,
+    /// Average color of each kind's top texture, for places that don't sample the atlas.
+    pub(crate) colors: HashMap<BlockKind, Color>,
+    /// Still fluid textures in the atlas, indexed by [`Fluid`].
+    pub(crate) fluid_still: [Option<AtlasRect>; 2],
+    /// Flowing fluid textures on their own, so they can scroll and wrap around.
+    pub(crate) fluid_flow: [Option<Image>; 2],
+    /// Crack textures in the atlas, indexed by break progress stage.
+    pub(crate) destroy_stages: [Option<AtlasRect>; 10],
+}
+
+/// A resource pack folder or zip file.
+enum PackSource {
+    Directory(PathBuf),
+    /// The archive and how many bytes have been unpacked from it so far.
+    Zip(ZipArchive<File>, u64),
+}
+
+impl PackSource {
+    fn open(path: &Path) -> Option<Self> {
+        if path.is_dir() {
+            return Some(PackSource::Directory(path.to_path_buf()));
+        }
+        let file = File::open(path).map_err(|e| warn!("Can't open resource pack {}: {}", path.display(), e)).ok()?;
+        match ZipArchive::new(file) {
+            Ok(archive) => Some(PackSource::Zip(archive, 0)),
+            Err(e) => {
+                warn!("{} is neither a folder nor a zip: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn read(&mut self, name: &str) -> Option<Vec<u8>> {
+        match self {
+            PackSource::Directory(root) => std::fs::read(root.join(name)).ok(),
+            PackSource::Zip(archive, unpacked) => {
+                let file = archive.by_name(name).ok()?;
+                let limit = MAX_ZIP_ENTRY_SIZE.min(MAX_ZIP_TOTAL_SIZE.saturating_sub(*unpacked));
+                let mut data = Vec::new();
+                file.take(limit + 1).read_to_end(&mut data).ok()?;
+                *unpacked += data.len() as u64;
+                if data.len() as u64 > limit {
+                    warn!("Skipping {} in resource pack, it unpacks to too much data", name);
+                    return None;
+                }
+                Some(data)
+            }
+        }
+    }
+
+    /// File names (relative to `dir`, without `extension`) directly inside `dir`.
+    fn list(&self, dir: &str, extension: &str) -> Vec<String> {
+        let names: Vec<String> = match self {
+            PackSource::Directory(root) => std::fs::read_dir(root.join(dir))
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            PackSource::Zip(archive, _) => archive
+                .file_names()
+                .filter_map(|name| name.strip_prefix(dir))
+                .filter(|name| !name.contains('/'))
+                .map(str::to_string)
+                .collect(),
+        };
+        names
+            .into_iter()
+            .filter_map(|name| name.strip_suffix(extension).map(str::to_string))
+            .collect()
+    }
+}
+
+/// Packs stacked like vanilla's pack list: a file in an earlier layer replaces the same
+/// file in the layers below it.
+struct PackStack(Vec<PackSource>);
+
+impl PackStack {
+    fn read(&mut self, name: &str) -> Option<Vec<u8>> {
+        self.0.iter_mut().find_map(|layer| layer.read(name))
+    }
+
+    fn list(&self, dir: &str, extension: &str) -> Vec<String> {
+        let mut names: Vec<String> = self.0.iter().flat_map(|layer| layer.list(dir, extension)).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[derive(Deserialize)]
+struct ModelFile {
+    parent: Option<String>,
+    #[serde(default)]
+    textures: HashMap<String, String>,
+    elements: Option<Vec<Element>>,
+}
+
+#[derive(Deserialize)]
+struct Element {
+    from: [f32; 3],
+    to: [f32; 3],
+    #[serde(default)]
+    faces: HashMap<String, ElementFace>,
+}
+
+#[derive(Deserialize)]
+struct ElementFace {
+    texture: String,
+    tintindex: Option<i32>,
+    uv: Option<[f32; 4]>,
+    #[serde(default)]
+    rotation: i32,
+}
+
+#[derive(Deserialize)]
+struct BlockStateFile {
+    #[serde(default)]
+    variants: HashMap<String, OneOrMany<Variant>>,
+    #[serde(default)]
+    multipart: Vec<MultipartCase>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    /// The first entry; weighted random variants always pick it.
+    fn first(&self) -> Option<&T> {
+        match self {
+            OneOrMany::One(value) => Some(value),
+            OneOrMany::Many(values) => values.first(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Variant {
+    model: String,
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    y: i32,
+    #[serde(default)]
+    uvlock: bool,
+}
+
+#[derive(Deserialize)]
+struct MultipartCase {
+    when: Option<Value>,
+    apply: OneOrMany<Variant>,
+}
+
+/// One face of a model element after texture variables are resolved.
+#[derive(Clone)]
+struct ModelFace {
+    texture: String,
+    tinted: bool,
+    /// Part of the texture shown, in texture pixels; reversed to mirror it.
+    uv: [f32; 4],
+    /// Quarter turns the texture is rotated clockwise.
+    rotation: u8,
+}
+
+/// A box of a model in sixteenths, with its faces in mesher order. Element rotations,
+/// like the tilt of a wall torch or the diagonals of a plant, aren't modelled.
+#[derive(Clone)]
+struct ModelElement {
+    from: [f32; 3],
+    to: [f32; 3],
+    faces: [Option<ModelFace>; 6],
+}
+
+/// Loads resource pack folders or zips, the first on top. Returns `None` if together
+/// they have no usable block textures.
+pub(crate) fn load_resource_pack(layers: &[PathBuf]) -> Option<ResourcePack> {
+    let mut source = PackStack(layers.iter().filter_map(|path| PackSource::open(path)).collect());
+
+    let (tiles, tile_names) = load_tiles(&mut source);
+    if tiles.is_empty() {
+        return None;
+    }
+    let layout = AtlasLayout::new(tiles.len() + 1, tiles[0].size);
+
+    let mut models: HashMap<String, Option<Vec<ModelElement>>> = HashMap::new();
+    let mut elements = HashMap::new();
+    let mut colors = HashMap::new();
+
+    for kind in BlockKind::ALL {
+        let Some(blockstate) = source
+            .read(&format!("{}{}.json", BLOCKSTATE_DIR, kind.to_str()))
+            .and_then(|data| parse_json::<BlockStateFile>(&data, kind.to_str()))
+        else {
+            continue;
+        };
+
+        for state in kind_states(kind) {
+            let mut state_elements = Vec::new();
+            for variant in select_variants(&blockstate, state) {
+                let model = models
+                    .entry(normalize(&variant.model))
+                    .or_insert_with_key(|name| resolve_model(&mut source, name));
+                if let Some(model) = model {
+                    state_elements.extend(model.iter().map(|element| rotate_element(element, variant)));
+                }
+            }
+            if state_elements.is_empty() {
+                continue;
+            }
+
+            if state == kind.to_state() {
+                let top = state_elements.iter().find_map(|element| element.faces[FACE_TOP].as_ref());
+                if let Some(tile) = top.and_then(|face| tile_names.get(&face.texture)) {
+                    colors.insert(kind, average_color(&tiles[*tile]));
+                }
+            }
+            let state_elements = state_elements
+                .into_iter()
+                .map(|element| BlockElement {
+                    model_box: ModelBox { from: element.from.map(|v| v / 16.0), to: element.to.map(|v| v / 16.0) },
+                    faces: element.faces.map(|face| {
+                        face.map(|face| FaceAppearance {
+                            texture: tile_names.get(&face.texture).map(|&tile| layout.rect(tile + 1, face.uv)),
+                            tinted: face.tinted,
+                            rotation: face.rotation,
+                        })
+                    }),
+                })
+                .collect();
+            elements.insert(state, state_elements);
+        }
+    }
+    debug!("Resolved models for {} block states ({} models)", elements.len(), models.len());
+
+    let fluids = [Fluid::Water, Fluid::Lava];
+    let fluid_still = fluids.map(|fluid| {
+        let tile = tile_names.get(&format!("block/{}_still", fluid.name()))?;
+        Some(layout.rect(tile + 1, FULL_UV))
+    });
+    let fluid_flow = fluids.map(|fluid| {
+        let name = format!("{}_flow", fluid.name());
+        let data = source.read(&format!("{}{}.png", TEXTURE_DIR, name))?;
+        let tile = decode_tile(&data, &name)?;
+        let mut image = Image::new(
+            Extent3d { width: tile.size, height: tile.size, depth_or_array_layers: 1 },
+            TextureDimension::D2,
+            tile.rgba,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+            address_mode_u: ImageAddressMode::Repeat,
+            address_mode_v: ImageAddressMode::Repeat,
+            ..ImageSamplerDescriptor::nearest()
+        });
+        Some(image)
+    });
+    let destroy_stages = std::array::from_fn(|stage| {
+        let tile = tile_names.get(&format!("block/destroy_stage_{}", stage))?;
+        Some(layout.rect(tile + 1, FULL_UV))
+    });
+
+    Some(ResourcePack {
+        atlas: layout.stitch(&tiles),
+        white: layout.rect(0, FULL_UV),
+        elements,
+        colors,
+        fluid_still,
+        fluid_flow,
+        destroy_stages,
+    })
+}
+
+fn parse_json<T: for<'de> Deserialize<'de>>(data: &[u8], name: &str) -> Option<T> {
+    serde_json::from_slice(data)
+        .map_err(|e| warn!("Ignoring malformed resource pack file {}: {}", name, e))
+        .ok()
+}
+
+/// Strips the default namespace so `minecraft:block/stone` and `block/stone` match.
+fn normalize(name: &str) -> String {
+    name.strip_prefix("minecraft:").unwrap_or(name).to_string()
+}
+
+/// All states of `kind`, found by walking the contiguous state id range from its default.
+fn kind_states(kind: BlockKind) -> impl Iterator<Item = BlockState> {
+    let default = kind.to_state().to_raw();
+    let down = (0..default).rev().map_while(move |raw| BlockState::from_raw(raw).filter(|state| state.to_kind() == kind));
+    let up = (default..=u16::MAX).map_while(move |raw| BlockState::from_raw(raw).filter(|state| state.to_kind() == kind));
+    down.chain(up)
+}
+
+/// The models `state` is drawn with: the first matching variant, or every multipart case
+/// that applies, like a fence's post and each of its connected sides.
+fn select_variants(blockstate: &BlockStateFile, state: BlockState) -> Vec<&Variant> {
+    let variant = blockstate
+        .variants
+        .iter()
+        .find(|(condition, _)| {
+            condition.is_empty()
+                || condition.split(',').all(|pair| match pair.split_once('=') {
+                    Some((name, value)) => property_matches(state, name, value),
+                    None => false,
+                })
+        })
+        .and_then(|(_, variants)| variants.first());
+    if let Some(variant) = variant {
+        return vec![variant];
+    }
+
+    blockstate
+        .multipart
+        .iter()
+        .filter(|case| case.when.as_ref().map_or(true, |when| when_matches(state, when)))
+        .filter_map(|case| case.apply.first())
+        .collect()
+}
+
+/// `value` may list alternatives as `a|b`.
+fn property_matches(state: BlockState, name: &str, value: &str) -> bool {
+    let Some(name) = PropName::from_str(name) else {
+        return false;
+    };
+    let Some(current) = state.get(name) else {
+        return false;
+    };
+    value.split('|').any(|value| PropValue::from_str(value) == Some(current))
+}
+
+fn when_matches(state: BlockState, when: &Value) -> bool {
+    let Some(conditions) = when.as_object() else {
+        return false;
+    };
+    if let Some(Value::Array(any)) = conditions.get("OR") {
+        return any.iter().any(|case| when_matches(state, case));
+    }
+    if let Some(Value::Array(all)) = conditions.get("AND") {
+        return all.iter().all(|case| when_matches(state, case));
+    }
+    conditions.iter().all(|(name, value)| match value {
+        Value::String(value) => property_matches(state, name, value),
+        Value::Bool(value) => property_matches(state, name, &value.to_string()),
+        _ => false,
+    })
+}
+
+/// Follows the parent chain of `name` and resolves the faces of its elements.
+fn resolve_model(source: &mut PackStack, name: &str) -> Option<Vec<ModelElement>> {
+    let mut textures: HashMap<String, String> = HashMap::new();
+    let mut elements = None;
+    let mut next = Some(name.to_string());
+
+    for _ in 0..MAX_MODEL_DEPTH {
+        let Some(current) = next.take() else {
+            break;
+        };
+        let data = source.read(&format!("{}{}.json", MODEL_DIR, current))?;
+        let model: ModelFile = parse_json(&data, &current)?;
+        // Children override their parents' texture variables.
+        for (key, value) in model.textures {
+            textures.entry(key).or_insert(value);
+        }
+        if elements.is_none() {
+            elements = model.elements;
+        }
+        next = model.parent.map(|parent| normalize(&parent));
+    }
+
+    let elements: Vec<ModelElement> = elements?
+        .into_iter()
+        .map(|element| ModelElement {
+            faces: std::array::from_fn(|index| {
+                let face = element.faces.get(FACE_NAMES[index])?;
+                Some(ModelFace {
+                    texture: resolve_texture(&textures, &face.texture)?,
+                    tinted: face.tintindex.is_some(),
+                    uv: face.uv.unwrap_or_else(|| default_uv(index, element.from, element.to)),
+                    rotation: (face.rotation.rem_euclid(360) / 90) as u8,
+                })
+            }),
+            from: element.from,
+            to: element.to,
+        })
+        .collect();
+    (!elements.is_empty()).then_some(elements)
+}
+
+/// Vanilla's UVs for a face without its own: the part of the texture it would show if
+/// the texture covered the whole side of the block.
+fn default_uv(face: usize, [x0, y0, z0]: [f32; 3], [x1, y1, z1]: [f32; 3]) -> [f32; 4] {
+    match face {
+        0 => [16.0 - z1, 16.0 - y1, 16.0 - z0, 16.0 - y0],
+        1 => [z0, 16.0 - y1, z1, 16.0 - y0],
+        2 => [x0, z0, x1, z1],
+        3 => [x0, 16.0 - z1, x1, 16.0 - z0],
+        4 => [x0, 16.0 - y1, x1, 16.0 - y0],
+        _ => [16.0 - x1, 16.0 - y1, 16.0 - x0, 16.0 - y0],
+    }
+}
+
+/// Follows `#variable` references to a texture name like `block/stone`.
+fn resolve_texture(textures: &HashMap<String, String>, reference: &str) -> Option<String> {
+    let mut reference = reference;
+    for _ in 0..MAX_MODEL_DEPTH {
+        match reference.strip_prefix('#') {
+            Some(variable) => reference = textures.get(variable)?,
+            None => return Some(normalize(reference)),
+        }
+    }
+    None
+}
+
+/// Turns a vector by a variant's rotation: around X first, then around Y.
+fn rotate_vector(mut v: [f32; 3], x: i32, y: i32) -> [f32; 3] {
+    for _ in 0..(x.rem_euclid(360) / 90) {
+        // North turns down.
+        v = [v[0], v[2], -v[1]];
+    }
+    for _ in 0..(y.rem_euclid(360) / 90) {
+        // Clockwise seen from above: north turns east.
+        v = [-v[2], v[1], v[0]];
+    }
+    v
+}
+
+/// Applies a variant's rotation to an element, moving each face to where it ends up.
+/// Textures turn along with their faces, unless `uvlock` keeps them aligned to the world.
+fn rotate_element(element: &ModelElement, variant: &Variant) -> ModelElement {
+    let (x, y) = (variant.x, variant.y);
+    let [a, b] = [element.from, element.to].map(|corner| rotate_vector(corner.map(|v| v - 8.0), x, y).map(|v| v + 8.0));
+    let from = std::array::from_fn(|axis| a[axis].min(b[axis]));
+    let to = std::array::from_fn(|axis| a[axis].max(b[axis]));
+
+    let mut faces: [Option<ModelFace>; 6] = Default::default();
+    for (index, face) in element.faces.iter().enumerate() {
+        let Some(face) = face else {
+            continue;
+        };
+        let normal = rotate_vector(NORMALS[index], x, y);
+        let target = NORMALS.iter().position(|n| *n == normal).expect("rotation keeps axis-aligned normals");
+        let mut face = face.clone();
+        if variant.uvlock {
+            face.uv = default_uv(target, from, to);
+        } else {
+            // Each clockwise quarter turn moves the texture's u axis from the face's u
+            // axis to its v axis, then on to -u and -v.
+            let u = rotate_vector(U_AXES[index], x, y);
+            let (target_u, target_v) = (U_AXES[target], V_AXES[target]);
+            let turns = [target_u, target_v, target_u.map(|v| -v), target_v.map(|v| -v)]
+                .iter()
+                .position(|axis| *axis == u)
+                .unwrap_or(0);
+            face.rotation = (face.rotation + turns as u8) % 4;
+        }
+        faces[target] = Some(face);
+    }
+    ModelElement { from, to, faces }
+}
+
+/// A decoded block texture, cut down to its first animation frame.
+struct Tile {
+    size: u32,
+    rgba: Vec<u8>,
+}
+
+/// Loads every block texture of the pack's size, indexed by name like `block/stone`.
+fn load_tiles(source: &mut PackStack) -> (Vec<Tile>, HashMap<String, usize>) {
+    let mut tiles: Vec<Tile> = Vec::new();
+    let mut names = HashMap::new();
+
+    let files = source.list(TEXTURE_DIR, ".png");
+    for file in files {
+        let Some(tile) = source.read(&format!("{}{}.png", TEXTURE_DIR, file)).and_then(|data| decode_tile(&data, &file)) else {
+            continue;
+        };
+        if tiles.first().is_some_and(|first| first.size != tile.size) {
+            debug!("Skipping {}: size {} differs from {}", file, tile.size, tiles[0].size);
+            continue;
+        }
+        names.insert(format!("block/{}", file), tiles.len());
+        tiles.push(tile);
+    }
+    (tiles, names)
+}
+
+fn decode_tile(data: &[u8], name: &str) -> Option<Tile> {
+    let image = match Image::from_buffer(
+        data,
+        ImageType::Extension("png"),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::RENDER_WORLD,
+    ) {
+        Ok(image) => image,
+        Err(e) => {
+            warn!("Can't decode texture {}: {}", name, e);
+            return None;
+        }
+    };
+    let image = image.convert(TextureFormat::Rgba8UnormSrgb)?;
+
+    // Animated textures stack their frames vertically; keep the first.
+    let size = image.width();
+    if image.height() < size {
+        return None;
+    }
+    let rgba = image.data[..(size * size * 4) as usize].to_vec();
+    Some(Tile { size, rgba })
+}
+
+fn average_color(tile: &Tile) -> Color {
+    let (mut sum, mut count) = ([0u32; 3], 0u32);
+    for pixel in tile.rgba.chunks_exact(4).filter(|pixel| pixel[3] > 0) {
+        for channel in 0..3 {
+            sum[channel] += pixel[channel] as u32;
+        }
+        count += 1;
+    }
+    let count = count.max(1);
+    Color::srgb_u8((sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8)
+}
+
+/// Square grid of equally sized tiles. Slot 0 is left white for untextured faces.
+struct AtlasLayout {
+    columns: u32,
+    tile_size: u32,
+}
+
+impl AtlasLayout {
+    fn new(slots: usize, tile_size: u32) -> Self {
+        Self {
+            columns: (slots as f32).sqrt().ceil() as u32,
+            tile_size,
+        }
+    }
+
+    fn size(&self) -> u32 {
+        self.columns * self.tile_size
+    }
+
+    /// Atlas coordinates of the `uv` part (in 0..16 texture pixels) of a slot, inset by
+    /// half a texel so neighbouring tiles don't bleed in.
+    fn rect(&self, slot: usize, [u0, v0, u1, v1]: [f32; 4]) -> AtlasRect {
+        let slot = slot as u32;
+        let scale = 1.0 / self.columns as f32;
+        let (left, top) = ((slot % self.columns) as f32 * scale, (slot / self.columns) as f32 * scale);
+        let texel = 0.5 / self.size() as f32;
+        let inset = |from: f32, to: f32| {
+            let (from, to) = (from / 16.0 * scale, to / 16.0 * scale);
+            let towards = if to >= from { texel } else { -texel };
+            (from + towards, to - towards)
+        };
+        let (u0, u1) = inset(u0, u1);
+        let (v0, v1) = inset(v0, v1);
+        [left + u0, top + v0, left + u1, top + v1]
+    }
+
+    fn stitch(&self, tiles: &[Tile]) -> Image {
+        let (size, tile_size) = (self.size(), self.tile_size);
+        let row_bytes = (tile_size * 4) as usize;
+        let mut data = vec![255; (size * size * 4) as usize];
+
+        for (index, tile) in tiles.iter().enumerate() {
+            let slot = index as u32 + 1;
+            let (column, row) = (slot % self.columns, slot / self.columns);
+            for y in 0..tile_size {
+                let src = y as usize * row_bytes;
+                let dst = (((row * tile_size + y) * size + column * tile_size) * 4) as usize;
+                data[dst..dst + row_bytes].copy_from_slice(&tile.rgba[src..src + row_bytes]);
+            }
+        }
+
+        let mut image = Image::new(
+            Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        image.sampler = ImageSampler::nearest();
+        image
+    }
+}
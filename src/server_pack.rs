@@ -0,0 +1,348 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::thread;
+use bevy::prelude::*;
+use sha1::{Digest, Sha1};
+use tokio::sync::mpsc;
+use valence_protocol::packets::play::ResourcePackStatusC2s;
+use crate::blocks::{base_pack_path, BlockAppearances};
+use crate::connection::ServerConnection;
+use crate::events::ClientCommand;
+use crate::rendering::ChunkMaterials;
+use crate::resource_pack::{load_resource_pack, ResourcePack};
+use crate::world::ChunkStore;
+
+/// Downloaded server packs, named by their SHA-1 so they're only fetched once.
+const SERVER_PACK_CACHE_DIR: &str = "server-resource-packs";
+/// Vanilla refuses server packs larger than this.
+const MAX_SERVER_PACK_SIZE: u64 = 250 * 1024 * 1024;
+
+const PROMPT_BACKGROUND: Color = Color::srgba(0.0, 0.0, 0.0, 0.8);
+const BUTTON: Color = Color::srgb(0.2, 0.22, 0.28);
+
+/// A resource pack the server asked us to use, from `ResourcePackSendS2c`.
+#[derive(Clone, Debug)]
+pub(crate) struct ResourcePackOffer {
+    pub(crate) url: String,
+    /// Lowercase hex SHA-1 of the pack; may be empty if the server doesn't know it.
+    pub(crate) hash: String,
+    pub(crate) forced: bool,
+    pub(crate) prompt: Option<String>,
+}
+
+/// The offer waiting for the player to accept or decline.
+#[derive(Resource, Default)]
+pub(crate) struct PendingPackOffer(pub(crate) Option<ResourcePackOffer>);
+
+/// Verified downloads, with the block textures of the server pack layered over ours if
+/// it has any.
+#[derive(Resource)]
+pub(crate) struct PackDownloadChannel {
+    sender: mpsc::Sender<Result<Option<ResourcePack>, String>>,
+    receiver: mpsc::Receiver<Result<Option<ResourcePack>, String>>,
+}
+
+impl Default for PackDownloadChannel {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel(4);
+        Self { sender, receiver }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct PackPrompt;
+
+#[derive(Component, Clone, Copy)]
+pub(crate) enum PackPromptButton {
+    Accept,
+    Decline,
+}
+
+pub(crate) fn update_pack_prompt(
+    mut commands: Commands,
+    offer: Res<PendingPackOffer>,
+    prompt_query: Query<Entity, With<PackPrompt>>,
+) {
+    if !offer.is_changed() {
+        return;
+    }
+    for entity in &prompt_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(offer) = &offer.0 else {
+        return;
+    };
+
+    let mut message = "The server recommends a custom resource pack.".to_string();
+    if offer.forced {
+        message = "The server requires a custom resource pack.".to_string();
+    }
+    if let Some(prompt) = &offer.prompt {
+        message = format!("{}\n{}", message, prompt);
+    }
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(16.0),
+                    ..default()
+                },
+                background_color: PROMPT_BACKGROUND.into(),
+                z_index: ZIndex::Global(20),
+                ..default()
+            },
+            PackPrompt,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(message, TextStyle { font_size: 24.0, color: Color::WHITE, ..default() })
+                    .with_text_justify(JustifyText::Center),
+            );
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(12.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|buttons| {
+                    spawn_button(buttons, "Accept", PackPromptButton::Accept);
+                    spawn_button(buttons, "Decline", PackPromptButton::Decline);
+                });
+        });
+}
+
+fn spawn_button(parent: &mut ChildBuilder, text: &str, action: PackPromptButton) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(16.0), Val::Px(6.0)),
+                    ..default()
+                },
+                background_color: BUTTON.into(),
+                ..default()
+            },
+            action,
+        ))
+        .with_children(|button| {
+            button.spawn(TextBundle::from_section(text, TextStyle { font_size: 20.0, color: Color::WHITE, ..default() }));
+        });
+}
+
+pub(crate) fn handle_pack_prompt_buttons(
+    interaction_query: Query<(&Interaction, &PackPromptButton), Changed<Interaction>>,
+    mut offer: ResMut<PendingPackOffer>,
+    connection: Res<ServerConnection>,
+    channel: Res<PackDownloadChannel>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(pending) = offer.0.take() else {
+            continue;
+        };
+
+        match button {
+            PackPromptButton::Accept => {
+                info!("Accepted server resource pack {}", pending.url);
+                connection.send(ClientCommand::ResourcePackStatus(ResourcePackStatusC2s::Accepted));
+
+                // A thread of its own, like LAN discovery: the connection holds a task pool
+                // thread for the whole session, and that may be the only one.
+                let sender = channel.sender.clone();
+                let spawned = thread::Builder::new().name("pack-download".to_string()).spawn(move || {
+                    let result = download_pack(&pending, Path::new(SERVER_PACK_CACHE_DIR), MAX_SERVER_PACK_SIZE).map(|path| {
+                        let layers: Vec<PathBuf> = std::iter::once(path).chain(base_pack_path()).collect();
+                        load_resource_pack(&layers)
+                    });
+                    let _ = sender.blocking_send(result);
+                });
+                if let Err(e) = spawned {
+                    warn!("Can't start the pack download: {}", e);
+                    connection.send(ClientCommand::ResourcePackStatus(ResourcePackStatusC2s::FailedDownload));
+                }
+            }
+            PackPromptButton::Decline => {
+                info!("Declined server resource pack {}", pending.url);
+                if pending.forced {
+                    warn!("The server requires this pack and will probably disconnect us");
+                }
+                connection.send(ClientCommand::ResourcePackStatus(ResourcePackStatusC2s::Declined));
+            }
+        }
+    }
+}
+
+pub(crate) fn receive_downloaded_packs(
+    mut channel: ResMut<PackDownloadChannel>,
+    connection: Res<ServerConnection>,
+    mut appearances: ResMut<BlockAppearances>,
+    chunk_materials: Res<ChunkMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    mut store: ResMut<ChunkStore>,
+) {
+    while let Ok(result) = channel.receiver.try_recv() {
+        let status = match result {
+            Ok(Some(pack)) => {
+                info!("Server resource pack loaded ({} block states)", pack.elements.len());
+                appearances.apply_pack(pack, &mut images);
+                chunk_materials.set_textures(&mut materials, &appearances);
+                store.mark_all_dirty();
+                ResourcePackStatusC2s::SuccessfullyLoaded
+            }
+            // Packs that only change sounds, language or the like are fine too.
+            Ok(None) => {
+                info!("Server resource pack has no block textures, keeping ours");
+                ResourcePackStatusC2s::SuccessfullyLoaded
+            }
+            Err(e) => {
+                warn!("Server resource pack failed: {}", e);
+                ResourcePackStatusC2s::FailedDownload
+            }
+        };
+        connection.send(ClientCommand::ResourcePackStatus(status));
+    }
+}
+
+/// Fetches the pack into `cache_dir` unless a copy with the right hash is already there.
+/// Protocol 763 has no "downloaded" status, so success is only reported once loaded.
+fn download_pack(offer: &ResourcePackOffer, cache_dir: &Path, max_size: u64) -> Result<PathBuf, String> {
+    let expected = offer.hash.to_ascii_lowercase();
+    let has_hash = expected.len() == 40 && expected.chars().all(|c| c.is_ascii_hexdigit());
+    let cache_name = if has_hash { expected.clone() } else { sha1_hex(offer.url.as_bytes()) };
+    let path = cache_dir.join(format!("{}.zip", cache_name));
+
+    if has_hash {
+        if let Ok(cached) = fs::read(&path) {
+            if sha1_hex(&cached) == expected {
+                info!("Using cached resource pack {}", path.display());
+                return Ok(path);
+            }
+            debug!("Cached {} is stale, downloading again", path.display());
+        }
+    }
+
+    info!("Downloading resource pack from {}", offer.url);
+    let response = ureq::get(&offer.url).call().map_err(|e| format!("download failed: {}", e))?;
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(max_size + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("download failed: {}", e))?;
+    if data.len() as u64 > max_size {
+        return Err(format!("pack is larger than {} bytes", max_size));
+    }
+
+    let actual = sha1_hex(&data);
+    if has_hash && actual != expected {
+        return Err(format!("hash mismatch: expected {}, got {}", expected, actual));
+    }
+    info!("Downloaded {} bytes (sha1 {})", data.len(), actual);
+
+    fs::create_dir_all(cache_dir).map_err(|e| format!("can't create cache: {}", e))?;
+    fs::write(&path, &data).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Cursor, Write};
+    use std::net::TcpListener;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+    use super::*;
+
+    fn pack_zip() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("pack.mcmeta", SimpleFileOptions::default()).unwrap();
+        zip.write_all(br#"{"pack":{"pack_format":15,"description":"test"}}"#).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// Serves `body` to a single request, then stops listening.
+    fn serve_once(body: Vec<u8>) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/pack.zip", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+            // The client may hang up early, as it does past the size cap.
+            let stream = reader.get_mut();
+            let _ = stream.write_all(header.as_bytes()).and_then(|()| stream.write_all(&body));
+        });
+        (url, server)
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bevy-client-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn offer(url: String, hash: String) -> ResourcePackOffer {
+        ResourcePackOffer { url, hash, forced: false, prompt: None }
+    }
+
+    #[test]
+    fn downloads_once_then_uses_cache() {
+        let zip = pack_zip();
+        let hash = sha1_hex(&zip);
+        let (url, server) = serve_once(zip.clone());
+        let cache = cache_dir("cache-reuse");
+
+        let path = download_pack(&offer(url.clone(), hash.clone()), &cache, MAX_SERVER_PACK_SIZE).unwrap();
+        server.join().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), zip);
+
+        // Nothing listens any more, so this only succeeds from the cache.
+        let cached = download_pack(&offer(url, hash), &cache, MAX_SERVER_PACK_SIZE).unwrap();
+        assert_eq!(cached, path);
+        let _ = fs::remove_dir_all(&cache);
+    }
+
+    #[test]
+    fn rejects_hash_mismatch() {
+        let (url, server) = serve_once(pack_zip());
+        let cache = cache_dir("hash-mismatch");
+
+        let result = download_pack(&offer(url, sha1_hex(b"another pack")), &cache, MAX_SERVER_PACK_SIZE);
+        server.join().unwrap();
+        assert!(result.unwrap_err().contains("hash mismatch"));
+        assert!(!cache.exists());
+    }
+
+    #[test]
+    fn rejects_packs_over_size_cap() {
+        let zip = pack_zip();
+        let hash = sha1_hex(&zip);
+        let (url, server) = serve_once(zip.clone());
+        let cache = cache_dir("size-cap");
+
+        let result = download_pack(&offer(url, hash), &cache, zip.len() as u64 - 1);
+        server.join().unwrap();
+        assert!(result.unwrap_err().contains("larger than"));
+        assert!(!cache.exists());
+    }
+}
@@ -0,0 +1,174 @@
+use std::f32::consts::TAU;
+use std::time::Instant;
+use bevy::color::{LinearRgba, Mix};
+use bevy::prelude::*;
+use crate::weather::{Weather, FLASH_AMBIENT};
+
+/// Server ticks per second; the world clock advances by this much locally between updates.
+const TICKS_PER_SECOND: f64 = 20.0;
+const TICKS_PER_DAY: i64 = 24000;
+/// Jumps larger than this, like `/time set`, are applied at once instead of eased towards.
+const SNAP_TICKS: f64 = 200.0;
+/// How quickly the drawn time catches up with the server's, per second.
+const CATCH_UP_RATE: f64 = 2.0;
+
+/// Light levels, tuned against the camera's default exposure.
+const SUN_ILLUMINANCE: f32 = 2000.0;
+const MOON_ILLUMINANCE: f32 = 150.0;
+const DAY_AMBIENT: f32 = 400.0;
+const NIGHT_AMBIENT: f32 = 30.0;
+const DAY_SKY: Color = Color::srgb(0.47, 0.65, 1.0);
+const NIGHT_SKY: Color = Color::srgb(0.0, 0.0, 0.02);
+
+/// The world clock from `WorldTimeUpdateS2c`, which the server sends about once a second.
+#[derive(Resource, Default)]
+pub(crate) struct WorldTime {
+    world_age: i64,
+    /// Ticks since the world began counting days; 0 is sunrise, 6000 noon.
+    time_of_day: i64,
+    /// False while the server has the daylight cycle turned off.
+    advancing: bool,
+    received: Option<Instant>,
+    /// The time of day the sky is drawn at, eased towards the server's.
+    displayed: Option<f64>,
+}
+
+impl WorldTime {
+    /// A negative time of day means the daylight cycle is stopped at its absolute value.
+    pub(crate) fn update(&mut self, world_age: i64, time_of_day: i64) {
+        self.world_age = world_age;
+        self.time_of_day = time_of_day.abs();
+        self.advancing = time_of_day >= 0;
+        self.received = Some(Instant::now());
+    }
+
+    /// Where the server's clock should be now, extrapolated from the last update.
+    fn server_time(&self) -> Option<f64> {
+        let received = self.received?;
+        let elapsed = if self.advancing { received.elapsed().as_secs_f64() * TICKS_PER_SECOND } else { 0.0 };
+        Some(self.time_of_day as f64 + elapsed)
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct Sun;
+
+#[derive(Component)]
+pub(crate) struct Moon;
+
+/// HUD line with the time of day, for checking when mobs can spawn.
+#[derive(Component)]
+pub(crate) struct ClockText;
+
+pub(crate) fn setup_sky(mut commands: Commands) {
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight { illuminance: SUN_ILLUMINANCE, ..default() },
+            transform: Transform::default().looking_to(Vec3::NEG_Y, Vec3::Z),
+            ..default()
+        },
+        Sun,
+    ));
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                illuminance: 0.0,
+                color: Color::srgb(0.7, 0.75, 1.0),
+                ..default()
+            },
+            transform: Transform::default().looking_to(Vec3::Y, Vec3::Z),
+            ..default()
+        },
+        Moon,
+    ));
+
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section("", TextStyle { font_size: 20.0, color: Color::WHITE, ..default() }),
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                right: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        },
+        ClockText,
+    ));
+}
+
+/// Moves the sun and moon and sets the ambient light and sky color for the current time,
+/// dimmed by bad weather.
+pub(crate) fn update_sky(
+    time: Res<Time>,
+    mut world_time: ResMut<WorldTime>,
+    weather: Res<Weather>,
+    mut clear_color: ResMut<ClearColor>,
+    mut ambient: ResMut<AmbientLight>,
+    mut sun_query: Query<(&mut Transform, &mut DirectionalLight), (With<Sun>, Without<Moon>)>,
+    mut moon_query: Query<(&mut Transform, &mut DirectionalLight), (With<Moon>, Without<Sun>)>,
+    mut clock_query: Query<&mut Text, With<ClockText>>,
+) {
+    let Some(target) = world_time.server_time() else {
+        return;
+    };
+    let displayed = match world_time.displayed {
+        Some(displayed) if (target - displayed).abs() < SNAP_TICKS => {
+            let step = if world_time.advancing { time.delta_seconds_f64() * TICKS_PER_SECOND } else { 0.0 };
+            let advanced = displayed + step;
+            advanced + (target - advanced) * (time.delta_seconds_f64() * CATCH_UP_RATE).min(1.0)
+        }
+        _ => target,
+    };
+    world_time.displayed = Some(displayed);
+
+    let angle = celestial_angle(displayed);
+    let daylight = ((angle * TAU).cos() * 2.0 + 0.5).clamp(0.0, 1.0) * weather.daylight_factor();
+    // The sun rises in the east (+X), is overhead at noon and sets in the west.
+    let sun_direction = Vec3::new(-(angle * TAU).sin(), (angle * TAU).cos(), 0.0);
+
+    for (mut transform, mut light) in &mut sun_query {
+        *transform = Transform::default().looking_to(-sun_direction, Vec3::Z);
+        light.illuminance = SUN_ILLUMINANCE * daylight;
+    }
+    for (mut transform, mut light) in &mut moon_query {
+        *transform = Transform::default().looking_to(sun_direction, Vec3::Z);
+        light.illuminance = MOON_ILLUMINANCE * (1.0 - daylight) * weather.daylight_factor();
+    }
+    ambient.brightness =
+        NIGHT_AMBIENT + (DAY_AMBIENT - NIGHT_AMBIENT) * daylight + FLASH_AMBIENT * weather.flash_strength();
+    let sky = LinearRgba::from(NIGHT_SKY).mix(&LinearRgba::from(DAY_SKY), daylight);
+    clear_color.0 = weather.storm_sky(sky).into();
+
+    for mut text in &mut clock_query {
+        text.sections[0].value = clock_text(&world_time, displayed, daylight);
+    }
+}
+
+/// Vanilla's sun angle as a fraction of a turn, 0 at noon. Days and nights are stretched
+/// slightly around sunrise and sunset.
+fn celestial_angle(time_of_day: f64) -> f32 {
+    let fraction = (time_of_day / TICKS_PER_DAY as f64 - 0.25).rem_euclid(1.0);
+    let eased = 0.5 - (fraction * std::f64::consts::PI).cos() / 2.0;
+    ((fraction * 2.0 + eased) / 3.0) as f32
+}
+
+fn clock_text(world_time: &WorldTime, displayed: f64, daylight: f32) -> String {
+    let ticks = displayed as i64;
+    let day = ticks / TICKS_PER_DAY + 1;
+    // Tick 0 is 06:00.
+    let minutes = ((ticks % TICKS_PER_DAY) * 1440 / TICKS_PER_DAY + 360) % 1440;
+    // What vanilla subtracts from sky light at this hour, which mob spawning goes by.
+    let darkening = ((1.0 - daylight) * 11.0) as u8;
+    let frozen = if world_time.advancing { "" } else { " (frozen)" };
+    format!(
+        "Day {}, {:02}:{:02}{}\nTime {} / age {}, sky light -{}",
+        day,
+        minutes / 60,
+        minutes % 60,
+        frozen,
+        world_time.time_of_day,
+        world_time.world_age,
+        darkening
+    )
+}
@@ -0,0 +1,183 @@
+use std::io::Read;
+use std::net::TcpStream;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use bevy::log::{debug, trace};
+use serde::Deserialize;
+use serde_json::Value;
+use valence_protocol::anyhow;
+use valence_protocol::decode::PacketFrame;
+use valence_protocol::packets::handshaking::handshake_c2s::{HandshakeC2s, HandshakeNextState};
+use valence_protocol::packets::status::{QueryPingC2s, QueryPongS2c, QueryRequestC2s, QueryResponseS2c};
+use valence_protocol::{Bounded, Packet, PacketDecoder, PacketEncoder, VarInt};
+use crate::connection::{resolve, split_host_port, PROTOCOL_VERSION};
+use crate::error::NetworkError;
+use crate::networking::{decode_packet, send_packet};
+
+/// How long a status query may take before the server is considered down.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a server reports about itself in the server list.
+#[derive(Clone, Debug)]
+pub(crate) struct ServerStatus {
+    pub(crate) motd: String,
+    pub(crate) version_name: String,
+    pub(crate) protocol: i32,
+    pub(crate) players_online: i32,
+    pub(crate) players_max: i32,
+    pub(crate) sample_players: Vec<String>,
+    /// PNG bytes of the 64x64 server icon.
+    pub(crate) favicon: Option<Vec<u8>>,
+    /// Round trip of the status ping packet.
+    pub(crate) latency: Duration,
+}
+
+#[derive(Deserialize)]
+struct StatusResponse {
+    version: StatusVersion,
+    players: Option<StatusPlayers>,
+    description: Option<Value>,
+    favicon: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StatusVersion {
+    name: String,
+    protocol: i32,
+}
+
+#[derive(Deserialize)]
+struct StatusPlayers {
+    max: i32,
+    online: i32,
+    #[serde(default)]
+    sample: Vec<StatusSamplePlayer>,
+}
+
+#[derive(Deserialize)]
+struct StatusSamplePlayer {
+    name: String,
+}
+
+/// Runs the status protocol against `address` (`host:port`): handshake with next state
+/// Status, status request, then a ping to measure latency.
+pub(crate) fn query_status(address: &str) -> Result<ServerStatus, NetworkError> {
+    let (host, port) = split_host_port(address)?;
+    let mut stream = TcpStream::connect_timeout(&resolve(host, port)?, STATUS_TIMEOUT)?;
+    stream.set_read_timeout(Some(STATUS_TIMEOUT))?;
+
+    let mut enc = PacketEncoder::new();
+    let mut dec = PacketDecoder::new();
+
+    send_packet(&mut enc, &mut stream, &HandshakeC2s {
+        protocol_version: VarInt(PROTOCOL_VERSION),
+        server_address: Bounded(host),
+        server_port: port,
+        next_state: HandshakeNextState::Status,
+    })?;
+    send_packet(&mut enc, &mut stream, &QueryRequestC2s)?;
+
+    let frame = read_frame(&mut dec, &mut stream)?;
+    expect_packet::<QueryResponseS2c>(&frame)?;
+    let response: QueryResponseS2c = decode_packet(&frame)?;
+    trace!("Status response from {}: {}", address, response.json);
+    let response: StatusResponse = serde_json::from_str(response.json)
+        .map_err(|e| NetworkError::Decode { packet: QueryResponseS2c::NAME, source: anyhow::Error::new(e) })?;
+
+    // Like vanilla, use the wall clock as payload so the pong can be matched up.
+    let payload = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+    let sent_at = Instant::now();
+    send_packet(&mut enc, &mut stream, &QueryPingC2s { payload })?;
+
+    let frame = read_frame(&mut dec, &mut stream)?;
+    expect_packet::<QueryPongS2c>(&frame)?;
+    let pong: QueryPongS2c = decode_packet(&frame)?;
+    let latency = sent_at.elapsed();
+    if pong.payload != payload {
+        debug!("Pong payload from {} doesn't match: {} != {}", address, pong.payload, payload);
+    }
+
+    let players = response.players;
+    Ok(ServerStatus {
+        motd: response.description.as_ref().map(flatten_text).unwrap_or_default(),
+        version_name: response.version.name,
+        protocol: response.version.protocol,
+        players_online: players.as_ref().map_or(0, |p| p.online),
+        players_max: players.as_ref().map_or(0, |p| p.max),
+        sample_players: players.map(|p| p.sample.into_iter().map(|s| s.name).collect()).unwrap_or_default(),
+        favicon: response.favicon.as_deref().and_then(decode_favicon),
+        latency,
+    })
+}
+
+/// `--ping <address>` entry point. Prints the status and returns the process exit code.
+pub(crate) fn ping_command(address: &str) -> i32 {
+    match query_status(address) {
+        Ok(status) => {
+            println!("{} is up ({} ms)", address, status.latency.as_millis());
+            println!("  MOTD:    {}", status.motd);
+            println!("  Version: {} (protocol {})", status.version_name, status.protocol);
+            println!("  Players: {}/{}", status.players_online, status.players_max);
+            for name in &status.sample_players {
+                println!("    {}", name);
+            }
+            if status.protocol != PROTOCOL_VERSION {
+                println!("  Note: this client speaks protocol {}", PROTOCOL_VERSION);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{} is down: {}", address, e);
+            1
+        }
+    }
+}
+
+fn read_frame(dec: &mut PacketDecoder, stream: &mut TcpStream) -> Result<PacketFrame, NetworkError> {
+    let mut buffer = [0; 4096];
+    loop {
+        if let Some(frame) = dec
+            .try_next_packet()
+            .map_err(|source| NetworkError::Decode { packet: "packet frame", source })?
+        {
+            return Ok(frame);
+        }
+
+        let size = stream.read(&mut buffer)?;
+        if size == 0 {
+            return Err(NetworkError::Closed);
+        }
+        dec.queue_bytes((&buffer[..size]).into());
+    }
+}
+
+fn expect_packet<P: Packet>(frame: &PacketFrame) -> Result<(), NetworkError> {
+    if frame.id == P::ID {
+        Ok(())
+    } else {
+        Err(NetworkError::UnexpectedPacket { state: P::STATE, id: frame.id })
+    }
+}
+
+/// Flattens a chat component (plain string, object with `text`/`extra`, or array) to plain text.
+fn flatten_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().map(flatten_text).collect(),
+        Value::Object(component) => {
+            let mut text = component.get("text").map(flatten_text).unwrap_or_default();
+            if let Some(extra) = component.get("extra") {
+                text.push_str(&flatten_text(extra));
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+/// Decodes a `data:image/png;base64,...` favicon into PNG bytes.
+fn decode_favicon(data_uri: &str) -> Option<Vec<u8>> {
+    let encoded = data_uri.strip_prefix("data:image/png;base64,")?;
+    let encoded: String = encoded.chars().filter(|c| !c.is_whitespace()).collect();
+    base64::engine::general_purpose::STANDARD.decode(encoded).ok()
+}
@@ -0,0 +1,219 @@
+use bevy::prelude::*;
+use valence_protocol::block::BlockKind;
+use valence_protocol::BlockState;
+use crate::block_models::{block_boxes, ModelBox};
+use crate::mesher::FACES;
+use crate::options::ClientOptions;
+use crate::world::ChunkStore;
+
+/// Added to each side of the outline so it isn't hidden by the block's own faces.
+const OUTLINE_MARGIN: f32 = 0.005;
+
+/// A block hit by a ray, and the face the ray entered it through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RayHit {
+    pub(crate) block: IVec3,
+    /// Index into [`FACES`].
+    pub(crate) face: usize,
+    /// Where the ray meets the face, in world coordinates.
+    pub(crate) point: Vec3,
+    /// From the ray's origin to `point`.
+    pub(crate) distance: f32,
+}
+
+impl RayHit {
+    pub(crate) fn normal(&self) -> IVec3 {
+        FACES[self.face].normal
+    }
+}
+
+/// The block under the crosshair, if one is in reach.
+#[derive(Resource, Default, PartialEq)]
+pub(crate) struct TargetedBlock(pub(crate) Option<RayHit>);
+
+/// Steps through the blocks along a ray one boundary crossing at a time and returns the
+/// first one whose shape the ray actually meets, so it passes beside torches and over
+/// slabs. The block the ray starts in is skipped, and the ray stops at unloaded chunks.
+pub(crate) fn raycast(store: &ChunkStore, origin: Vec3, direction: Vec3, reach: f32) -> Option<RayHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+    let mut block = origin.floor().as_ivec3();
+    let step = IVec3::from_array(std::array::from_fn(|axis| if direction[axis] > 0.0 { 1 } else { -1 }));
+    // Distance along the ray between two boundaries, and to the next one, per axis.
+    let t_delta = Vec3::from_array(std::array::from_fn(|axis| 1.0 / direction[axis].abs()));
+    let mut t_max = Vec3::from_array(std::array::from_fn(|axis| {
+        if direction[axis] == 0.0 {
+            return f32::INFINITY;
+        }
+        let to_boundary = if direction[axis] > 0.0 {
+            block[axis] as f32 + 1.0 - origin[axis]
+        } else {
+            origin[axis] - block[axis] as f32
+        };
+        to_boundary * t_delta[axis]
+    }));
+
+    loop {
+        let axis = if t_max.x <= t_max.y && t_max.x <= t_max.z {
+            0
+        } else if t_max.y <= t_max.z {
+            1
+        } else {
+            2
+        };
+        if t_max[axis] > reach {
+            return None;
+        }
+        block[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+
+        let state = store.block(block)?;
+        if !is_targetable(state) {
+            continue;
+        }
+        let local_origin = origin - block.as_vec3();
+        let nearest = target_boxes(state)
+            .iter()
+            .filter_map(|model_box| intersect_box(model_box, local_origin, direction))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((distance, face)) = nearest {
+            // Anything further along is further away still.
+            return (distance <= reach).then(|| RayHit { block, face, point: origin + direction * distance, distance });
+        }
+    }
+}
+
+/// Air and plain fluids are looked through, as in vanilla.
+fn is_targetable(state: BlockState) -> bool {
+    !state.is_air() && !matches!(state.to_kind(), BlockKind::Water | BlockKind::Lava)
+}
+
+/// The boxes the ray is tested against; a block drawn without any, like a wall with
+/// neither post nor sides, still gets the whole block.
+fn target_boxes(state: BlockState) -> Vec<ModelBox> {
+    let boxes = block_boxes(state);
+    if boxes.is_empty() {
+        vec![ModelBox::FULL]
+    } else {
+        boxes
+    }
+}
+
+/// Where a ray from `origin` enters `model_box`, and through which face.
+fn intersect_box(model_box: &ModelBox, origin: Vec3, direction: Vec3) -> Option<(f32, usize)> {
+    let (mut enter, mut exit, mut face) = (0.0_f32, f32::INFINITY, 0);
+    for axis in 0..3 {
+        let (from, to) = (model_box.from[axis], model_box.to[axis]);
+        if direction[axis] == 0.0 {
+            if origin[axis] < from || origin[axis] > to {
+                return None;
+            }
+            continue;
+        }
+        let (near, far) = if direction[axis] > 0.0 { (from, to) } else { (to, from) };
+        let t_near = (near - origin[axis]) / direction[axis];
+        let t_far = (far - origin[axis]) / direction[axis];
+        if t_near > enter {
+            enter = t_near;
+            face = axis * 2 + usize::from(direction[axis] > 0.0);
+        }
+        exit = exit.min(t_far);
+    }
+    (enter <= exit).then_some((enter, face))
+}
+
+/// Finds the block the camera is looking at.
+pub(crate) fn update_targeted_block(
+    store: Res<ChunkStore>,
+    options: Res<ClientOptions>,
+    camera_query: Query<&Transform, With<Camera3d>>,
+    mut target: ResMut<TargetedBlock>,
+) {
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+    let hit = raycast(&store, camera.translation, *camera.forward(), options.reach);
+    target.set_if_neq(TargetedBlock(hit));
+}
+
+/// Outlines each box of the targeted block, so a stair shows its steps.
+pub(crate) fn draw_target_outline(target: Res<TargetedBlock>, store: Res<ChunkStore>, mut gizmos: Gizmos) {
+    let Some(hit) = target.0 else {
+        return;
+    };
+    let Some(state) = store.block(hit.block) else {
+        return;
+    };
+    for model_box in target_boxes(state) {
+        let (from, to) = (Vec3::from_array(model_box.from), Vec3::from_array(model_box.to));
+        let transform = Transform::from_translation(hit.block.as_vec3() + (from + to) / 2.0)
+            .with_scale(to - from + Vec3::splat(OUTLINE_MARGIN * 2.0));
+        gizmos.cuboid(transform, Color::BLACK);
+    }
+}
+
+/// HUD line describing the targeted block, for checking redstone state at a glance.
+#[derive(Component)]
+pub(crate) struct TargetText;
+
+pub(crate) fn setup_target_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section("", TextStyle { font_size: 20.0, color: Color::WHITE, ..default() }),
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                left: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        },
+        TargetText,
+    ));
+}
+
+pub(crate) fn update_target_hud(
+    target: Res<TargetedBlock>,
+    store: Res<ChunkStore>,
+    mut text_query: Query<&mut Text, With<TargetText>>,
+) {
+    let description = match target.0.and_then(|hit| Some((hit, store.block(hit.block)?))) {
+        Some((hit, state)) => {
+            let (block, normal) = (hit.block, hit.normal());
+            format!(
+                "{}\nat {} {} {}, face {} {} {}, {:.1} blocks away",
+                describe_state(state),
+                block.x,
+                block.y,
+                block.z,
+                normal.x,
+                normal.y,
+                normal.z,
+                hit.distance
+            )
+        }
+        None => String::new(),
+    };
+    for mut text in &mut text_query {
+        if text.sections[0].value != description {
+            text.sections[0].value = description.clone();
+        }
+    }
+}
+
+/// A state written the way commands take it, like `minecraft:lever[face=wall,facing=north,powered=true]`.
+fn describe_state(state: BlockState) -> String {
+    let kind = state.to_kind();
+    let properties: Vec<String> = kind
+        .props()
+        .iter()
+        .filter_map(|&name| Some(format!("{}={}", name.to_str(), state.get(name)?.to_str())))
+        .collect();
+    if properties.is_empty() {
+        format!("minecraft:{}", kind.to_str())
+    } else {
+        format!("minecraft:{}[{}]", kind.to_str(), properties.join(","))
+    }
+}
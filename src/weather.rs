@@ -0,0 +1,285 @@
+use bevy::color::{LinearRgba, Mix};
+use bevy::math::Affine2;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::{ImageAddressMode, ImageSampler, ImageSamplerDescriptor};
+use crate::mesher::MeshBuilder;
+use crate::registry::Registries;
+use crate::world::ChunkStore;
+
+/// Rain and snow are drawn in the columns this many blocks around the camera.
+const PRECIPITATION_RADIUS: i32 = 10;
+/// Half the height of the precipitation volume.
+const PRECIPITATION_HALF_HEIGHT: f32 = 10.0;
+/// Blocks one copy of the precipitation texture spans vertically.
+const TEXTURE_SPAN: f32 = 4.0;
+const TEXTURE_WIDTH: u32 = 16;
+const TEXTURE_HEIGHT: u32 = 64;
+/// Texture copies per second the precipitation falls.
+const RAIN_SPEED: f32 = 3.0;
+const SNOW_SPEED: f32 = 0.4;
+/// Biomes colder than this get snow instead of rain, as in vanilla.
+const SNOW_TEMPERATURE: f32 = 0.15;
+
+/// How quickly rain and thunder fade in and out, per second.
+const FADE_RATE: f32 = 0.5;
+const LIGHTNING_FLASH_SECONDS: f32 = 0.25;
+const FLASH_SKY: LinearRgba = LinearRgba::rgb(0.8, 0.8, 1.0);
+/// Ambient brightness added at the peak of a lightning flash.
+pub(crate) const FLASH_AMBIENT: f32 = 800.0;
+
+/// Weather changes from `GameStateChangeS2c`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum WeatherChange {
+    BeginRain,
+    EndRain,
+    RainLevel(f32),
+    ThunderLevel(f32),
+}
+
+/// Current rain and thunder, faded towards the levels the server last sent.
+#[derive(Resource, Default)]
+pub(crate) struct Weather {
+    rain_target: f32,
+    thunder_target: f32,
+    rain: f32,
+    thunder: f32,
+    /// Seconds left of the current lightning flash.
+    flash: f32,
+}
+
+impl Weather {
+    pub(crate) fn apply(&mut self, change: WeatherChange) {
+        match change {
+            WeatherChange::BeginRain => self.rain_target = 1.0,
+            WeatherChange::EndRain => {
+                self.rain_target = 0.0;
+                self.thunder_target = 0.0;
+            }
+            WeatherChange::RainLevel(level) => self.rain_target = level.clamp(0.0, 1.0),
+            WeatherChange::ThunderLevel(level) => self.thunder_target = level.clamp(0.0, 1.0),
+        }
+    }
+
+    pub(crate) fn strike_lightning(&mut self) {
+        self.flash = LIGHTNING_FLASH_SECONDS;
+    }
+
+    /// How much of the daylight gets through the clouds; rain and thunder each take up
+    /// to 5/16 of it, like vanilla's sky darkening.
+    pub(crate) fn daylight_factor(&self) -> f32 {
+        (1.0 - self.rain * 5.0 / 16.0) * (1.0 - self.thunder * 5.0 / 16.0)
+    }
+
+    /// 1 right as lightning strikes, fading to 0.
+    pub(crate) fn flash_strength(&self) -> f32 {
+        (self.flash / LIGHTNING_FLASH_SECONDS).clamp(0.0, 1.0)
+    }
+
+    /// Greys out the sky as vanilla does for rain and thunder, and lights it up during a flash.
+    pub(crate) fn storm_sky(&self, sky: LinearRgba) -> LinearRgba {
+        let luma = sky.red * 0.3 + sky.green * 0.59 + sky.blue * 0.11;
+        let gray = |brightness: f32| LinearRgba::rgb(luma * brightness, luma * brightness, luma * brightness);
+        sky.mix(&gray(0.6), self.rain * 0.75)
+            .mix(&gray(0.2), self.thunder * 0.75)
+            .mix(&FLASH_SKY, self.flash_strength() * 0.45)
+    }
+}
+
+/// The volume of falling rain or snow that follows the camera.
+#[derive(Component)]
+pub(crate) struct Precipitation;
+
+#[derive(Resource)]
+pub(crate) struct PrecipitationMaterials {
+    rain: Handle<StandardMaterial>,
+    snow: Handle<StandardMaterial>,
+}
+
+/// Builds the precipitation volume: two crossed quads per column, all in one mesh, with
+/// the falling done by scrolling the texture.
+pub(crate) fn setup_precipitation(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let mut builder = MeshBuilder::default();
+    let height = PRECIPITATION_HALF_HEIGHT;
+    for dx in -PRECIPITATION_RADIUS..=PRECIPITATION_RADIUS {
+        for dz in -PRECIPITATION_RADIUS..=PRECIPITATION_RADIUS {
+            if dx * dx + dz * dz > PRECIPITATION_RADIUS * PRECIPITATION_RADIUS {
+                continue;
+            }
+            let (x, z) = (dx as f32 + 0.5, dz as f32 + 0.5);
+            // Shift each column's texture so the drops don't line up.
+            let offset = (hash(dx as u32 ^ (dz as u32).rotate_left(16)) & 0xff) as f32 / 256.0;
+            let (top, bottom) = (offset, offset + 2.0 * height / TEXTURE_SPAN);
+            let uvs = [[0.0, bottom], [0.0, top], [1.0, top], [1.0, bottom]];
+            let colors = [[1.0; 4]; 4];
+            builder.push_quad(
+                [[x - 0.5, -height, z], [x - 0.5, height, z], [x + 0.5, height, z], [x + 0.5, -height, z]],
+                [0.0, 0.0, 1.0],
+                uvs,
+                colors,
+                false,
+            );
+            builder.push_quad(
+                [[x, -height, z - 0.5], [x, height, z - 0.5], [x, height, z + 0.5], [x, -height, z + 0.5]],
+                [1.0, 0.0, 0.0],
+                uvs,
+                colors,
+                false,
+            );
+        }
+    }
+    let Some(mesh) = builder.build() else {
+        return;
+    };
+
+    let mut material = |texture: Image| {
+        materials.add(StandardMaterial {
+            base_color_texture: Some(images.add(texture)),
+            alpha_mode: AlphaMode::Blend,
+            cull_mode: None,
+            double_sided: true,
+            unlit: true,
+            ..default()
+        })
+    };
+    let precipitation_materials = PrecipitationMaterials {
+        rain: material(precipitation_texture(false)),
+        snow: material(precipitation_texture(true)),
+    };
+
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(mesh),
+            material: precipitation_materials.rain.clone(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Precipitation,
+    ));
+    commands.insert_resource(precipitation_materials);
+}
+
+/// Fades the weather, and shows rain or snow around the camera depending on the biome.
+/// Precipitation thins out where the sky isn't visible, since it doesn't stop at roofs.
+pub(crate) fn update_weather(
+    time: Res<Time>,
+    mut weather: ResMut<Weather>,
+    store: Res<ChunkStore>,
+    registries: Res<Registries>,
+    precipitation_materials: Res<PrecipitationMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    camera_query: Query<&Transform, (With<Camera3d>, Without<Precipitation>)>,
+    mut volume_query: Query<(&mut Transform, &mut Handle<StandardMaterial>, &mut Visibility), With<Precipitation>>,
+) {
+    // Only written while something fades, so clear weather doesn't count as a change.
+    let step = time.delta_seconds() * FADE_RATE;
+    let rain = approach(weather.rain, weather.rain_target, step);
+    let thunder = approach(weather.thunder, weather.thunder_target, step);
+    let flash = (weather.flash - time.delta_seconds()).max(0.0);
+    if (rain, thunder, flash) != (weather.rain, weather.thunder, weather.flash) {
+        weather.rain = rain;
+        weather.thunder = thunder;
+        weather.flash = flash;
+    }
+
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+    let Ok((mut transform, mut handle, mut visibility)) = volume_query.get_single_mut() else {
+        return;
+    };
+
+    let block = camera.translation.floor().as_ivec3();
+    let biome = store.biome(block).and_then(|id| registries.biome(id));
+    let snowing = match biome {
+        Some(biome) if biome.has_precipitation => biome.temperature < SNOW_TEMPERATURE,
+        _ => {
+            visibility.set_if_neq(Visibility::Hidden);
+            return;
+        }
+    };
+    let open_sky = store.light(block).map_or(1.0, |(_, sky)| sky as f32 / 15.0);
+    let density = weather.rain * open_sky;
+    if density <= 0.01 {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    }
+
+    visibility.set_if_neq(Visibility::Visible);
+    // Snapped to the block grid so the columns stay put as the camera moves.
+    let translation = Vec3::new(block.x as f32, camera.translation.y, block.z as f32);
+    if transform.translation != translation {
+        transform.translation = translation;
+    }
+    let (material, speed) = if snowing {
+        (&precipitation_materials.snow, SNOW_SPEED)
+    } else {
+        (&precipitation_materials.rain, RAIN_SPEED)
+    };
+    if *handle != *material {
+        *handle = material.clone();
+    }
+    if let Some(material) = materials.get_mut(material) {
+        material.base_color = Color::WHITE.with_alpha(density);
+        material.uv_transform = Affine2::from_translation(Vec2::new(0.0, -(time.elapsed_seconds() * speed).fract()));
+    }
+}
+
+fn approach(current: f32, target: f32, step: f32) -> f32 {
+    if current < target {
+        (current + step).min(target)
+    } else {
+        (current - step).max(target)
+    }
+}
+
+fn hash(value: u32) -> u32 {
+    let value = value.wrapping_mul(2654435761);
+    value ^ (value >> 15)
+}
+
+/// A tileable texture of streaks for rain, or scattered flakes for snow.
+fn precipitation_texture(snow: bool) -> Image {
+    let mut data = vec![0; (TEXTURE_WIDTH * TEXTURE_HEIGHT * 4) as usize];
+    let mut put = |x: u32, y: u32, rgba: [u8; 4]| {
+        let index = (((y % TEXTURE_HEIGHT) * TEXTURE_WIDTH + x % TEXTURE_WIDTH) * 4) as usize;
+        data[index..index + 4].copy_from_slice(&rgba);
+    };
+    for x in 0..TEXTURE_WIDTH {
+        for drop in 0..3 {
+            let random = hash(x * 7 + drop * 131 + snow as u32 * 1009);
+            let y = random % TEXTURE_HEIGHT;
+            if snow {
+                let flake = [255, 255, 255, 230];
+                put(x, y, flake);
+                put(x + 1, y, flake);
+                put(x, y + 1, flake);
+            } else if random & 0x10000 != 0 {
+                let length = 4 + (random >> 20) % 8;
+                for step in 0..length {
+                    put(x, y + step, [170, 190, 230, 160]);
+                }
+            }
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d { width: TEXTURE_WIDTH, height: TEXTURE_HEIGHT, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        ..ImageSamplerDescriptor::nearest()
+    });
+    image
+}
@@ -1,52 +1,439 @@
-use bevy::asset::Assets;
-use bevy::color::Color;
-use bevy::pbr::{PbrBundle, StandardMaterial};
-use bevy::prelude::{default, Commands, Cuboid, Mesh, Res, ResMut, Resource, Transform};
-use crate::WorldCube;
-use bevy::prelude::DetectChanges;
-
-#[derive(Resource)]
-struct WorldData {
-    // Store world data here.  For example, a 2D grid of block colors:
-    blocks: Vec<Vec<Vec<Color>>>,
-    width: usize,
-    height: usize,
-    depth: usize
-}
-
-fn update_world_mesh(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>, // Use StandardMaterial
-    world_data: Res<WorldData>,
-) {
-    if world_data.is_changed() {
-        // Clear existing cubes
-
-
-        if !world_data.blocks.is_empty() {
-            let width = world_data.width;
-            let height = world_data.height;
-            let depth = world_data.depth; // Assuming you have depth in your world data
-
-            for x in 0..width {
-                for y in 0..height {
-                    for z in 0..depth {
-                        let color = world_data.blocks[x][y][z]; // Access 3D block data
-
-                        commands.spawn((
-                            PbrBundle { // Use PbrBundle for 3D
-                                mesh: meshes.add(Cuboid::new(1.0, 1.0, 1.0)), // Create a cube mesh
-                                material: materials.add(color), // Use the block color as material
-                                transform: Transform::from_xyz(x as f32, y as f32, z as f32),
-                                ..default()
-                            },
-                            WorldCube { x, y, z },
-                        ));
-                    }
+use std::collections::{HashMap, HashSet};
+use bevy::math::{IVec2, IVec3};
+use bevy::prelude::Resource;
+use valence_protocol::anyhow::{self, bail};
+use valence_protocol::{BlockState, Decode, GameMode, VarInt};
+
+pub(crate) const SECTION_SIZE: i32 = 16;
+const SECTION_VOLUME: usize = 16 * 16 * 16;
+const BIOMES_PER_SECTION: usize = 4 * 4 * 4;
+const LIGHT_ARRAY_LEN: usize = SECTION_VOLUME / 2;
+
+/// The dimension we're in, from `GameJoinS2c`.
+#[derive(Resource, Clone, Debug)]
+pub(crate) struct DimensionInfo {
+    pub(crate) dimension_name: String,
+    pub(crate) dimension_type: String,
+    /// Lowest block y; chunk sections are stacked upwards from here.
+    pub(crate) min_y: i32,
+    pub(crate) height: i32,
+    pub(crate) has_skylight: bool,
+    pub(crate) hashed_seed: i64,
+    pub(crate) view_distance: i32,
+    pub(crate) simulation_distance: i32,
+    pub(crate) game_mode: GameMode,
+}
+
+impl Default for DimensionInfo {
+    fn default() -> Self {
+        Self {
+            dimension_name: "minecraft:overworld".to_string(),
+            dimension_type: "minecraft:overworld".to_string(),
+            min_y: -64,
+            height: 384,
+            has_skylight: true,
+            hashed_seed: 0,
+            view_distance: 10,
+            simulation_distance: 10,
+            game_mode: GameMode::Survival,
+        }
+    }
+}
+
+impl DimensionInfo {
+    pub(crate) fn section_count(&self) -> usize {
+        (self.height / SECTION_SIZE).max(0) as usize
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum SectionBlocks {
+    /// Every block in the section is the same, typically air.
+    Uniform(BlockState),
+    /// Indexed by `(y * 16 + z) * 16 + x`, like the protocol.
+    Mixed(Box<[BlockState]>),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ChunkSection {
+    pub(crate) blocks: SectionBlocks,
+    /// Biome ids in 4x4x4 cells, same ordering as the blocks.
+    pub(crate) biomes: [u16; BIOMES_PER_SECTION],
+}
+
+impl ChunkSection {
+    pub(crate) fn block(&self, x: usize, y: usize, z: usize) -> BlockState {
+        match &self.blocks {
+            SectionBlocks::Uniform(state) => *state,
+            SectionBlocks::Mixed(blocks) => blocks[(y * 16 + z) * 16 + x],
+        }
+    }
+
+    pub(crate) fn set_block(&mut self, x: usize, y: usize, z: usize, state: BlockState) {
+        if let SectionBlocks::Uniform(uniform) = self.blocks {
+            if uniform == state {
+                return;
+            }
+            self.blocks = SectionBlocks::Mixed(vec![uniform; SECTION_VOLUME].into_boxed_slice());
+        }
+        if let SectionBlocks::Mixed(blocks) = &mut self.blocks {
+            blocks[(y * 16 + z) * 16 + x] = state;
+        }
+    }
+
+    pub(crate) fn biome(&self, x: usize, y: usize, z: usize) -> u16 {
+        self.biomes[((y / 4) * 4 + z / 4) * 4 + x / 4]
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        matches!(self.blocks, SectionBlocks::Uniform(state) if state.is_air())
+    }
+}
+
+/// Light levels of one 16³ section, four bits per block.
+#[derive(Clone, Debug)]
+pub(crate) enum LightNibbles {
+    Uniform(u8),
+    /// Indexed like blocks; even indices in the low nibble.
+    Data(Box<[u8; LIGHT_ARRAY_LEN]>),
+}
+
+impl LightNibbles {
+    pub(crate) fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+        match self {
+            LightNibbles::Uniform(level) => *level,
+            LightNibbles::Data(data) => {
+                let index = (y * 16 + z) * 16 + x;
+                (data[index / 2] >> ((index % 2) * 4)) & 0xf
+            }
+        }
+    }
+}
+
+/// Light sections of a chunk as sent by the server. There is one more section than
+/// block sections at each end, so index 0 lies just below `min_y`. `None` means the
+/// server didn't include that section.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LightData {
+    pub(crate) sky: Vec<Option<LightNibbles>>,
+    pub(crate) block: Vec<Option<LightNibbles>>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Chunk {
+    /// Bottom to top, starting at the dimension's `min_y`.
+    pub(crate) sections: Vec<ChunkSection>,
+    /// Indexed like [`LightData`], with sections the server left out filled in.
+    pub(crate) sky_light: Vec<LightNibbles>,
+    pub(crate) block_light: Vec<LightNibbles>,
+}
+
+impl Chunk {
+    /// Builds a chunk from its packet. Sky light the server left out is assumed to be
+    /// open sky in dimensions that have one.
+    pub(crate) fn new(sections: Vec<ChunkSection>, light: LightData, has_skylight: bool) -> Self {
+        let light_sections = sections.len() + 2;
+        let sky_default = if has_skylight { 15 } else { 0 };
+        let fill = |data: Vec<Option<LightNibbles>>, default: u8| {
+            let mut data = data;
+            data.resize(light_sections, None);
+            data.into_iter().map(|nibbles| nibbles.unwrap_or(LightNibbles::Uniform(default))).collect()
+        };
+        Self {
+            sky_light: fill(light.sky, sky_default),
+            block_light: fill(light.block, 0),
+            sections,
+        }
+    }
+}
+
+/// All loaded chunks, plus the sections whose meshes are out of date.
+#[derive(Resource, Default)]
+pub(crate) struct ChunkStore {
+    chunks: HashMap<IVec2, Chunk>,
+    min_y: i32,
+    /// Section positions (chunk x, section index, chunk z) waiting to be re-meshed.
+    pub(crate) dirty: HashSet<IVec3>,
+}
+
+impl ChunkStore {
+    pub(crate) fn min_y(&self) -> i32 {
+        self.min_y
+    }
+
+    /// Forgets every chunk, e.g. when entering a new dimension. Pending re-meshes are
+    /// dropped too, so the caller must throw away existing section meshes itself.
+    pub(crate) fn reset(&mut self, min_y: i32) {
+        self.chunks.clear();
+        self.dirty.clear();
+        self.min_y = min_y;
+    }
+
+    /// Queues every loaded section for re-meshing, e.g. after the block textures changed.
+    pub(crate) fn mark_all_dirty(&mut self) {
+        for (pos, chunk) in &self.chunks {
+            for index in 0..chunk.sections.len() as i32 {
+                self.dirty.insert(IVec3::new(pos.x, index, pos.y));
+            }
+        }
+    }
+
+    pub(crate) fn chunk(&self, pos: IVec2) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub(crate) fn insert_chunk(&mut self, pos: IVec2, chunk: Chunk) {
+        let section_count = chunk.sections.len() as i32;
+        self.chunks.insert(pos, chunk);
+        // Neighbours may have faces along the shared border that are now hidden.
+        for offset in [IVec2::ZERO, IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            let neighbour = pos + offset;
+            if offset == IVec2::ZERO || self.chunks.contains_key(&neighbour) {
+                for index in 0..section_count {
+                    self.dirty.insert(IVec3::new(neighbour.x, index, neighbour.y));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn remove_chunk(&mut self, pos: IVec2) {
+        if let Some(chunk) = self.chunks.remove(&pos) {
+            for index in 0..chunk.sections.len() as i32 {
+                self.dirty.insert(IVec3::new(pos.x, index, pos.y));
+            }
+        }
+    }
+
+    /// Section position (chunk x, section index, chunk z) and local coordinates of a block.
+    pub(crate) fn locate(&self, block: IVec3) -> (IVec3, [usize; 3]) {
+        let y = block.y - self.min_y;
+        let section = IVec3::new(
+            block.x.div_euclid(SECTION_SIZE),
+            y.div_euclid(SECTION_SIZE),
+            block.z.div_euclid(SECTION_SIZE),
+        );
+        let local = [
+            block.x.rem_euclid(SECTION_SIZE) as usize,
+            y.rem_euclid(SECTION_SIZE) as usize,
+            block.z.rem_euclid(SECTION_SIZE) as usize,
+        ];
+        (section, local)
+    }
+
+    pub(crate) fn section(&self, section: IVec3) -> Option<&ChunkSection> {
+        if section.y < 0 {
+            return None;
+        }
+        self.chunks
+            .get(&IVec2::new(section.x, section.z))?
+            .sections
+            .get(section.y as usize)
+    }
+
+    /// Block and sky light at a world position, `None` if that chunk isn't loaded. Light
+    /// reaches one section beyond the world's bottom and top.
+    pub(crate) fn light(&self, block: IVec3) -> Option<(u8, u8)> {
+        let (section, [x, y, z]) = self.locate(block);
+        let chunk = self.chunks.get(&IVec2::new(section.x, section.z))?;
+        let index = usize::try_from(section.y + 1).ok()?;
+        let block_light = chunk.block_light.get(index)?.get(x, y, z);
+        let sky_light = chunk.sky_light.get(index)?.get(x, y, z);
+        Some((block_light, sky_light))
+    }
+
+    /// Applies a `LightUpdateS2c`, re-meshing the affected sections and their neighbours.
+    pub(crate) fn update_light(&mut self, pos: IVec2, light: LightData) {
+        let Some(chunk) = self.chunks.get_mut(&pos) else {
+            return;
+        };
+        let mut changed = Vec::new();
+        for (target, updates) in [(&mut chunk.sky_light, light.sky), (&mut chunk.block_light, light.block)] {
+            for (index, nibbles) in updates.into_iter().enumerate() {
+                if let (Some(slot), Some(nibbles)) = (target.get_mut(index), nibbles) {
+                    *slot = nibbles;
+                    changed.push(index as i32 - 1);
+                }
+            }
+        }
+
+        for index in changed {
+            for offset in [IVec3::ZERO, IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z] {
+                let section = IVec3::new(pos.x, index, pos.y) + offset;
+                if self.section(section).is_some() {
+                    self.dirty.insert(section);
                 }
             }
         }
     }
+
+    /// Block at a world position, `None` if that chunk isn't loaded or it's out of the world.
+    pub(crate) fn block(&self, block: IVec3) -> Option<BlockState> {
+        let (section, [x, y, z]) = self.locate(block);
+        self.section(section).map(|section| section.block(x, y, z))
+    }
+
+    /// Biome id at a world position, `None` if that chunk isn't loaded or it's out of the world.
+    pub(crate) fn biome(&self, block: IVec3) -> Option<u16> {
+        let (section, [x, y, z]) = self.locate(block);
+        self.section(section).map(|section| section.biome(x, y, z))
+    }
+
+    pub(crate) fn set_block(&mut self, block: IVec3, state: BlockState) {
+        let (section_pos, [x, y, z]) = self.locate(block);
+        if section_pos.y < 0 {
+            return;
+        }
+        let Some(section) = self
+            .chunks
+            .get_mut(&IVec2::new(section_pos.x, section_pos.z))
+            .and_then(|chunk| chunk.sections.get_mut(section_pos.y as usize))
+        else {
+            return;
+        };
+        section.set_block(x, y, z, state);
+
+        self.dirty.insert(section_pos);
+        // A block on the section border can hide or reveal faces next door.
+        for (local, axis) in [(x, IVec3::X), (y, IVec3::Y), (z, IVec3::Z)] {
+            if local == 0 {
+                self.dirty.insert(section_pos - axis);
+            } else if local == 15 {
+                self.dirty.insert(section_pos + axis);
+            }
+        }
+    }
+}
+
+/// Collects the light arrays of a chunk or light update packet by their masks. Bit `i`
+/// of a mask refers to light section `i`; arrays come in the order of the set bits.
+pub(crate) fn parse_light(
+    sky_mask: &[u64],
+    block_mask: &[u64],
+    empty_sky_mask: &[u64],
+    empty_block_mask: &[u64],
+    sky_arrays: &[[u8; LIGHT_ARRAY_LEN]],
+    block_arrays: &[[u8; LIGHT_ARRAY_LEN]],
+    light_sections: usize,
+) -> LightData {
+    let bit = |mask: &[u64], index: usize| mask.get(index / 64).is_some_and(|bits| bits >> (index % 64) & 1 != 0);
+    let collect = |mask: &[u64], empty_mask: &[u64], arrays: &[[u8; LIGHT_ARRAY_LEN]]| {
+        let mut arrays = arrays.iter();
+        (0..light_sections)
+            .map(|index| {
+                if bit(mask, index) {
+                    arrays.next().map(|array| LightNibbles::Data(Box::new(*array)))
+                } else if bit(empty_mask, index) {
+                    Some(LightNibbles::Uniform(0))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    };
+    LightData {
+        sky: collect(sky_mask, empty_sky_mask, sky_arrays),
+        block: collect(block_mask, empty_block_mask, block_arrays),
+    }
 }
 
+/// Parses the `blocks_and_biomes` field of `ChunkDataS2c` into `section_count` sections.
+pub(crate) fn parse_chunk_sections(mut data: &[u8], section_count: usize) -> anyhow::Result<Vec<ChunkSection>> {
+    let r = &mut data;
+    let mut sections = Vec::with_capacity(section_count);
+
+    for _ in 0..section_count {
+        let _non_air_blocks = i16::decode(r)?;
+
+        let blocks = match read_paletted_container(r, SECTION_VOLUME, 4, 8)? {
+            PalettedValues::Single(id) => SectionBlocks::Uniform(block_state(id)),
+            PalettedValues::Many(ids) => SectionBlocks::Mixed(ids.into_iter().map(block_state).collect()),
+        };
+
+        let mut biomes = [0; BIOMES_PER_SECTION];
+        match read_paletted_container(r, BIOMES_PER_SECTION, 1, 3)? {
+            PalettedValues::Single(id) => biomes.fill(id as u16),
+            PalettedValues::Many(ids) => {
+                for (biome, id) in biomes.iter_mut().zip(ids) {
+                    *biome = id as u16;
+                }
+            }
+        }
+
+        sections.push(ChunkSection { blocks, biomes });
+    }
+
+    Ok(sections)
+}
+
+fn block_state(id: u32) -> BlockState {
+    BlockState::from_raw(id as u16).unwrap_or(BlockState::AIR)
+}
+
+enum PalettedValues {
+    Single(u32),
+    Many(Vec<u32>),
+}
+
+/// Reads one paletted container. Bits up to `max_indirect_bits` use a local palette (never
+/// fewer than `min_indirect_bits`), anything above indexes the global registry directly.
+fn read_paletted_container(
+    r: &mut &[u8],
+    entries: usize,
+    min_indirect_bits: u8,
+    max_indirect_bits: u8,
+) -> anyhow::Result<PalettedValues> {
+    let bits = u8::decode(r)?;
+    // Entries are read out of a u64 with a mask of `bits` ones; real servers never use
+    // more than 15 bits for block states.
+    if bits > 32 {
+        bail!("paletted container uses {} bits per entry", bits);
+    }
+
+    if bits == 0 {
+        let value = VarInt::decode(r)?.0 as u32;
+        let data_len = VarInt::decode(r)?.0;
+        for _ in 0..data_len {
+            i64::decode(r)?;
+        }
+        return Ok(PalettedValues::Single(value));
+    }
+
+    let palette = if bits <= max_indirect_bits {
+        let len = VarInt::decode(r)?.0.max(0) as usize;
+        let max_len = entries.min(1 << bits.max(min_indirect_bits));
+        if len > max_len {
+            bail!("paletted container has a palette of {} for {} entries at {} bits", len, entries, bits);
+        }
+        let mut palette = Vec::with_capacity(len);
+        for _ in 0..len {
+            palette.push(VarInt::decode(r)?.0 as u32);
+        }
+        Some(palette)
+    } else {
+        None
+    };
+    let bits = if palette.is_some() { bits.max(min_indirect_bits) } else { bits } as usize;
+
+    let data_len = VarInt::decode(r)?.0.max(0) as usize;
+    let per_long = 64 / bits;
+    if data_len != entries.div_ceil(per_long) {
+        bail!("paletted container has {} longs for {} entries at {} bits", data_len, entries, bits);
+    }
+    let mut longs = Vec::with_capacity(data_len);
+    for _ in 0..data_len {
+        longs.push(i64::decode(r)? as u64);
+    }
+
+    let mask = (1u64 << bits) - 1;
+    let values = (0..entries)
+        .map(|i| {
+            let raw = ((longs[i / per_long] >> ((i % per_long) * bits)) & mask) as u32;
+            match &palette {
+                Some(palette) => palette.get(raw as usize).copied().unwrap_or(0),
+                None => raw,
+            }
+        })
+        .collect();
+
+    Ok(PalettedValues::Many(values))
+}